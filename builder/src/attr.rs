@@ -0,0 +1,386 @@
+// Parsing of the `#[builder(...)]` attributes on a struct and on its fields.
+//
+// Attributes other than `builder` are ignored. A struct or field may carry any
+// number of `builder` attributes, each with a comma separated list of keys;
+// every key may be given at most once in total.
+
+use proc_macro2::{Span, TokenStream};
+use quote::{quote, ToTokens};
+use syn::{Attribute, Expr, Field, Ident, Lit, LitStr, Meta, NestedMeta, Path, Type, Visibility};
+
+use crate::{is_option, EachItem, Pattern};
+
+#[derive(Default)]
+pub(crate) struct FieldAttr {
+    pub(crate) each: Option<Ident>,
+    // what the `each` setter adds, given with `each(item = "...")` or told
+    // from the field's type
+    pub(crate) each_item: Option<EachItem>,
+    pub(crate) default: Option<FieldDefault>,
+    pub(crate) setter_into: bool,
+    pub(crate) keep_option: bool,
+    pub(crate) sub_builder: bool,
+    pub(crate) name: Option<Ident>,
+    // `from_str = false` leaves the field out of `set_from_str`
+    pub(crate) skip_from_str: bool,
+    // environment variable `build` reads the field from when it is not set
+    pub(crate) env: Option<LitStr>,
+    // the field is left out of the builder and built with `Default`
+    pub(crate) skip: bool,
+    pub(crate) setter_name: Option<Ident>,
+    pub(crate) setter_prefix: Option<String>,
+    // also generate a `try_` setter converting with `TryInto`
+    pub(crate) try_setter: bool,
+    // checks the value `build` resolves the field to
+    pub(crate) validate: Option<Path>,
+}
+
+pub(crate) enum FieldDefault {
+    Trait,
+    // the expression and how it was written, for the builder's docs
+    Expr(Box<Expr>, String),
+    // `default_with = "path", depends_on(a, b)` calls `path(&a, &b)` once `a`
+    // and `b` are resolved
+    With { path: Path, depends_on: Vec<Ident> },
+}
+
+impl FieldDefault {
+    pub(crate) fn depends_on(&self) -> &[Ident] {
+        match self {
+            FieldDefault::With { depends_on, .. } => depends_on,
+            _ => &[],
+        }
+    }
+
+    // How the default is described in the builder's docs.
+    pub(crate) fn describe(&self) -> String {
+        match self {
+            FieldDefault::Trait => "defaults to `Default::default()`".to_owned(),
+            FieldDefault::Expr(_, source) => format!("defaults to `{}`", source),
+            FieldDefault::With { path, depends_on } => {
+                let path = path.segments.iter().map(|s| s.ident.to_string()).collect::<Vec<_>>().join("::");
+                let deps = depends_on.iter().map(|d| format!("`{}`", d)).collect::<Vec<_>>();
+                if deps.is_empty() {
+                    format!("computed by `{}`", path)
+                } else {
+                    format!("computed by `{}` from {}", path, deps.join(", "))
+                }
+            }
+        }
+    }
+}
+
+impl ToTokens for FieldDefault {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        match self {
+            FieldDefault::Trait => tokens.extend(quote!(::core::default::Default::default())),
+            FieldDefault::Expr(e, _) => e.to_tokens(tokens),
+            FieldDefault::With { path, depends_on } => {
+                let locals = depends_on.iter().map(resolved_local);
+                tokens.extend(quote!(#path(#(&#locals),*)));
+            }
+        }
+    }
+}
+
+// The local `build` resolves a field a `default_with` depends on into.
+pub(crate) fn resolved_local(name: &Ident) -> Ident {
+    Ident::new(&format!("__{}", name), Span::call_site())
+}
+
+#[derive(Clone, Default)]
+pub(crate) struct StructAttr {
+    pub(crate) typestate: bool,
+    pub(crate) pattern: Option<Pattern>,
+    pub(crate) validate: Option<Path>,
+    pub(crate) from_str: bool,
+    // `derive_impl` defaults it to the visibility of the struct
+    pub(crate) vis: Option<Visibility>,
+    pub(crate) name: Option<Ident>,
+    pub(crate) no_std: bool,
+    // set by `#[builder]` on a function, whose builder also gets `call`
+    pub(crate) call: bool,
+}
+
+impl StructAttr {
+    // The crate the generated code takes `Vec` and `String` from, `alloc` in
+    // `no_std` mode. Everything else comes from `core`.
+    pub(crate) fn alloc(&self) -> TokenStream {
+        if self.no_std {
+            quote!(::alloc)
+        } else {
+            quote!(::std)
+        }
+    }
+}
+
+const FIELD_KEYS: &[&str] = &["each", "default", "setter", "sub_builder", "name", "from_str", "env", "skip", "try_setter", "default_with", "depends_on", "validate"];
+const EACH_KEYS: &[&str] = &["name", "item"];
+const SETTER_KEYS: &[&str] = &["into", "strip_option", "name", "prefix"];
+const STRUCT_KEYS: &[&str] = &["typestate", "pattern", "build_fn", "from_str", "vis", "name", "no_std"];
+const BUILD_FN_KEYS: &[&str] = &["validate"];
+
+pub(crate) fn parse_field_attr(f: &Field) -> syn::Result<FieldAttr> {
+    let mut ret = FieldAttr::default();
+    let mut keys = Keys::new(FIELD_KEYS);
+    let mut depends_on = vec![];
+    let mut each_item = None;
+    for meta in builder_entries(&f.attrs)? {
+        match keys.check(&meta)? {
+            "each" => {
+                if let Meta::List(_) = meta {
+                    let mut each_keys = Keys::new(EACH_KEYS);
+                    for meta in expect_list(&meta, "each(...)")? {
+                        match each_keys.check(&meta)? {
+                            "name" => ret.each = Some(expect_str(&meta, r#"name = "...""#)?.parse()?),
+                            "item" => each_item = Some(expect_str(&meta, r#"item = "...""#)?.parse()?),
+                            _ => unreachable!(),
+                        }
+                    }
+                    if ret.each.is_none() {
+                        return Err(syn::Error::new_spanned(meta, r#"expected `each(name = "...", item = "...")`"#));
+                    }
+                } else {
+                    let form = r#"each = "..."` or `each(name = "...", item = "...")"#;
+                    ret.each = Some(expect_str(&meta, form)?.parse()?);
+                }
+            }
+            "default" => {
+                let default = match meta {
+                    Meta::Path(_) => FieldDefault::Trait,
+                    _ => {
+                        let lstr = expect_str(&meta, r#"default` or `default = "...""#)?;
+                        FieldDefault::Expr(Box::new(lstr.parse()?), lstr.value())
+                    }
+                };
+                ret.default = Some(default);
+            }
+            "setter" => {
+                let mut setter_keys = Keys::new(SETTER_KEYS);
+                for meta in expect_list(&meta, "setter(...)")? {
+                    match setter_keys.check(&meta)? {
+                        "into" => {
+                            expect_path(&meta, "into")?;
+                            ret.setter_into = true;
+                        }
+                        "strip_option" => ret.keep_option = !expect_bool(&meta, "strip_option = false")?,
+                        "name" => ret.setter_name = Some(expect_str(&meta, r#"name = "...""#)?.parse()?),
+                        "prefix" => ret.setter_prefix = Some(expect_str(&meta, r#"prefix = "...""#)?.value()),
+                        _ => unreachable!(),
+                    }
+                }
+                setter_keys.conflict("name", "prefix")?;
+                if let Some(span) = setter_keys.span("strip_option") {
+                    if !is_option(&f.ty) {
+                        return Err(syn::Error::new(span, "`strip_option` only applies to `Option` fields"));
+                    }
+                }
+            }
+            "sub_builder" => {
+                expect_path(&meta, "sub_builder")?;
+                if !matches!(f.ty, Type::Path(_)) {
+                    return Err(syn::Error::new_spanned(&f.ty, "`sub_builder` expects a struct deriving `Builder`"));
+                }
+                ret.sub_builder = true;
+            }
+            "name" => ret.name = Some(expect_str(&meta, r#"name = "...""#)?.parse()?),
+            "from_str" => ret.skip_from_str = !expect_bool(&meta, "from_str = false")?,
+            "env" => ret.env = Some(expect_str(&meta, r#"env = "...""#)?),
+            "skip" => {
+                expect_path(&meta, "skip")?;
+                ret.skip = true;
+            }
+            "try_setter" => {
+                expect_path(&meta, "try_setter")?;
+                ret.try_setter = true;
+            }
+            "default_with" => {
+                let path = expect_str(&meta, r#"default_with = "...""#)?.parse()?;
+                ret.default = Some(FieldDefault::With { path, depends_on: vec![] });
+            }
+            "depends_on" => {
+                for meta in expect_list(&meta, "depends_on(...)")? {
+                    match meta.path().get_ident() {
+                        Some(ident) if matches!(meta, Meta::Path(_)) => depends_on.push(ident.clone()),
+                        _ => return Err(syn::Error::new_spanned(meta, "expected a field name")),
+                    }
+                }
+            }
+            "validate" => ret.validate = Some(expect_str(&meta, r#"validate = "...""#)?.parse()?),
+            _ => unreachable!(),
+        }
+    }
+
+    keys.conflict("default_with", "default")?;
+    keys.conflict("default_with", "each")?;
+    keys.conflict("default_with", "sub_builder")?;
+    match (&mut ret.default, keys.span("depends_on")) {
+        (Some(FieldDefault::With { depends_on: deps, .. }), _) => *deps = depends_on,
+        (_, Some(span)) => return Err(syn::Error::new(span, "`depends_on` only applies to `default_with`")),
+        _ => {}
+    }
+    if ret.each.is_some() {
+        let item = match each_item {
+            Some(ty) => Some(EachItem::from_item(ty)),
+            None => EachItem::infer(&f.ty),
+        };
+        let msg = r#"can not tell the item type of this collection, give it with `each(name = "...", item = "...")`"#;
+        ret.each_item = Some(item.ok_or_else(|| syn::Error::new_spanned(&f.ty, msg))?);
+    }
+    keys.conflict("default", "each")?;
+    keys.conflict("sub_builder", "each")?;
+    keys.conflict("sub_builder", "default")?;
+    keys.conflict("env", "each")?;
+    keys.conflict("env", "sub_builder")?;
+    keys.conflict("try_setter", "each")?;
+    keys.conflict("try_setter", "sub_builder")?;
+    if let Some(span) = keys.span("skip") {
+        if keys.seen.len() > 1 {
+            return Err(syn::Error::new(span, "`skip` can not be used together with other builder attributes"));
+        }
+    }
+    Ok(ret)
+}
+
+pub(crate) fn parse_struct_attr(attrs: &[Attribute]) -> syn::Result<StructAttr> {
+    let mut ret = StructAttr::default();
+    let mut keys = Keys::new(STRUCT_KEYS);
+    for meta in builder_entries(attrs)? {
+        match keys.check(&meta)? {
+            "typestate" => {
+                expect_path(&meta, "typestate")?;
+                ret.typestate = true;
+            }
+            "pattern" => ret.pattern = Some(Pattern::parse(&expect_str(&meta, r#"pattern = "...""#)?)?),
+            "build_fn" => {
+                let mut build_fn_keys = Keys::new(BUILD_FN_KEYS);
+                for meta in expect_list(&meta, "build_fn(...)")? {
+                    match build_fn_keys.check(&meta)? {
+                        "validate" => ret.validate = Some(expect_str(&meta, r#"validate = "...""#)?.parse()?),
+                        _ => unreachable!(),
+                    }
+                }
+            }
+            "from_str" => {
+                expect_path(&meta, "from_str")?;
+                ret.from_str = true;
+            }
+            "vis" => ret.vis = Some(expect_str(&meta, r#"vis = "...""#)?.parse()?),
+            "name" => ret.name = Some(expect_str(&meta, r#"name = "...""#)?.parse()?),
+            "no_std" => {
+                expect_path(&meta, "no_std")?;
+                ret.no_std = true;
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    if let (Some(_), Some(span)) = (keys.span("typestate"), keys.span("pattern")) {
+        return Err(syn::Error::new(span, "typestate builders always use the owned pattern"));
+    }
+    if let (Some(_), Some(span)) = (keys.span("typestate"), keys.span("from_str")) {
+        return Err(syn::Error::new(span, "`from_str` is not supported by typestate builders"));
+    }
+    Ok(ret)
+}
+
+// The entries of all `#[builder(...)]` attributes in `attrs`, in order.
+fn builder_entries(attrs: &[Attribute]) -> syn::Result<Vec<Meta>> {
+    let mut ret = vec![];
+    for attr in attrs.iter().filter(|a| a.path.is_ident("builder")) {
+        match attr.parse_meta()? {
+            Meta::List(l) => ret.extend(nested_metas(l.nested)?),
+            meta => return Err(syn::Error::new_spanned(meta, "expected `builder(...)`")),
+        }
+    }
+    Ok(ret)
+}
+
+fn nested_metas<I: IntoIterator<Item = NestedMeta>>(nested: I) -> syn::Result<Vec<Meta>> {
+    nested
+        .into_iter()
+        .map(|nested| match nested {
+            NestedMeta::Meta(meta) => Ok(meta),
+            NestedMeta::Lit(lit) => Err(syn::Error::new_spanned(lit, "expected a builder attribute, found a literal")),
+        })
+        .collect()
+}
+
+// The keys seen so far in one attribute list.
+struct Keys {
+    known: &'static [&'static str],
+    seen: Vec<(&'static str, Span)>,
+}
+
+impl Keys {
+    fn new(known: &'static [&'static str]) -> Self {
+        Keys { known, seen: vec![] }
+    }
+
+    // Returns the key of `meta`, rejecting unknown and repeated keys.
+    fn check(&mut self, meta: &Meta) -> syn::Result<&'static str> {
+        let path = meta.path();
+        let key = path
+            .get_ident()
+            .and_then(|ident| self.known.iter().find(|k| ident == *k))
+            .copied();
+        let key = match key {
+            Some(key) => key,
+            None => {
+                let name = path.segments.iter().map(|s| s.ident.to_string()).collect::<Vec<_>>().join("::");
+                return Err(syn::Error::new_spanned(path, format!("unknown builder attribute `{}`", name)));
+            }
+        };
+        if self.span(key).is_some() {
+            return Err(syn::Error::new_spanned(path, format!("duplicate builder attribute `{}`", key)));
+        }
+        self.seen.push((key, path.get_ident().unwrap().span()));
+        Ok(key)
+    }
+
+    fn span(&self, key: &str) -> Option<Span> {
+        self.seen.iter().find(|(k, _)| *k == key).map(|(_, span)| *span)
+    }
+
+    // Rejects `key` if `other` was given as well.
+    fn conflict(&self, key: &str, other: &str) -> syn::Result<()> {
+        match (self.span(key), self.span(other)) {
+            (Some(span), Some(_)) => Err(syn::Error::new(span, format!("`{}` can not be used together with `{}`", key, other))),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn expect_path(meta: &Meta, form: &str) -> syn::Result<()> {
+    match meta {
+        Meta::Path(_) => Ok(()),
+        _ => Err(syn::Error::new_spanned(meta, format!("expected `{}`", form))),
+    }
+}
+
+fn expect_list(meta: &Meta, form: &str) -> syn::Result<Vec<Meta>> {
+    match meta {
+        Meta::List(l) => nested_metas(l.nested.clone()),
+        _ => Err(syn::Error::new_spanned(meta, format!("expected `{}`", form))),
+    }
+}
+
+fn expect_str(meta: &Meta, form: &str) -> syn::Result<LitStr> {
+    match meta {
+        Meta::NameValue(nv) => match &nv.lit {
+            Lit::Str(lstr) => Ok(lstr.clone()),
+            lit => Err(syn::Error::new_spanned(lit, "expected a string literal")),
+        },
+        _ => Err(syn::Error::new_spanned(meta, format!("expected `{}`", form))),
+    }
+}
+
+fn expect_bool(meta: &Meta, form: &str) -> syn::Result<bool> {
+    match meta {
+        Meta::NameValue(nv) => match &nv.lit {
+            Lit::Bool(b) => Ok(b.value),
+            lit => Err(syn::Error::new_spanned(lit, "expected `true` or `false`")),
+        },
+        _ => Err(syn::Error::new_spanned(meta, format!("expected `{}`", form))),
+    }
+}
@@ -0,0 +1,182 @@
+// Code generation for `#[builder(from_str)]`.
+//
+// `set_from_str` sets a field by name from its textual value, parsed with
+// `FromStr`, so builders can be filled from environment variables, config
+// files or `--set key=value` flags. `each` fields append the parsed item, maps
+// take `key=value` items, and the fields of a `sub_builder` are reached with
+// dotted keys such as `server.port`.
+
+use proc_macro2::{Span, TokenStream, TokenTree};
+use quote::{quote, quote_spanned, ToTokens};
+use syn::spanned::Spanned;
+use syn::{Generics, LitStr, Type};
+
+use crate::{each_flag, extract_ty_from_option, is_option, sub_error_ty, BuilderTarget, EachItem};
+
+// Whether `ty` names one of the type parameters of `generics`.
+fn uses_type_param(ty: &Type, generics: &Generics) -> bool {
+    fn visit(tokens: TokenStream, generics: &Generics) -> bool {
+        tokens.into_iter().any(|token| match token {
+            TokenTree::Ident(ident) => generics.type_params().any(|t| t.ident == ident),
+            TokenTree::Group(group) => visit(group.stream(), generics),
+            _ => false,
+        })
+    }
+    visit(ty.to_token_stream(), generics)
+}
+
+pub(crate) fn expand(target: &BuilderTarget) -> TokenStream {
+    let BuilderTarget {
+        generics,
+        builder_name,
+        error_name,
+        vis,
+        fields,
+        attrs,
+        struct_attr,
+        ..
+    } = target;
+    let alloc = struct_attr.alloc();
+
+    let fields: Vec<_> = fields.iter().filter(|f| !attrs[f.ident.as_ref().unwrap()].skip_from_str).collect();
+
+    // every type parsed from a string, for the bounds of `set_from_str`
+    let mut parsed_tys = vec![];
+    // parsing goes through `__FromStrField`, called at the field, so a type
+    // that can not be parsed is reported there, suggesting to skip the field
+    let parse = |ty: &Type, field: &Type, lit: &LitStr, value: TokenStream| {
+        let parsed = quote_spanned!(field.span()=> <#ty as __FromStrField>::__from_str(#value));
+        quote! {
+            #parsed.map_err(|message| #error_name::Parse {
+                field: #alloc::string::String::from(#lit),
+                message,
+            })?
+        }
+    };
+
+    let arms: Vec<TokenStream> = fields.iter().filter(|f| !attrs[f.ident.as_ref().unwrap()].sub_builder).map(|f| {
+        let name = f.ident.as_ref().unwrap();
+        let lit = LitStr::new(&name.to_string(), Span::call_site());
+        let set = if attrs[name].each.is_some() {
+            let flag = each_flag(name);
+            match attrs[name].each_item.as_ref().unwrap() {
+                EachItem::Single(item_ty) => {
+                    let item = parse(item_ty, &f.ty, &lit, quote!(value));
+                    parsed_tys.push(item_ty.clone());
+                    quote! {
+                        ::core::iter::Extend::extend(&mut self.#name, ::core::iter::once(#item));
+                        self.#flag = true;
+                    }
+                }
+                EachItem::Pair(k, v) => {
+                    let key = parse(k, &f.ty, &lit, quote!(key));
+                    let item = parse(v, &f.ty, &lit, quote!(item));
+                    parsed_tys.push(k.clone());
+                    parsed_tys.push(v.clone());
+                    quote! {
+                        let (key, item) = value.split_once('=').ok_or_else(|| #error_name::Parse {
+                            field: #alloc::string::String::from(#lit),
+                            message: #alloc::string::String::from("expected `key=value`"),
+                        })?;
+                        ::core::iter::Extend::extend(&mut self.#name, ::core::iter::once((#key, #item)));
+                        self.#flag = true;
+                    }
+                }
+            }
+        } else {
+            // `Option` fields parse their inner type
+            let ty = extract_ty_from_option(f.ty.clone());
+            let value = parse(&ty, &f.ty, &lit, quote!(value));
+            parsed_tys.push(ty);
+            if is_option(&f.ty) {
+                quote!(self.#name = ::core::option::Option::Some(::core::option::Option::Some(#value));)
+            } else {
+                quote!(self.#name = ::core::option::Option::Some(#value);)
+            }
+        };
+        quote!(#lit => { #set })
+    }).collect();
+
+    let sub_builders = fields.iter().filter(|f| attrs[f.ident.as_ref().unwrap()].sub_builder).map(|f| {
+        let name = f.ident.as_ref().unwrap();
+        let prefix = LitStr::new(&format!("{}.", name), Span::call_site());
+        let lit = LitStr::new(&name.to_string(), Span::call_site());
+        let sub_error = sub_error_ty(&f.ty);
+        quote! {
+            if let ::core::option::Option::Some(key) = key.strip_prefix(#prefix) {
+                self.#name.set_from_str(key, value).map_err(|e| match e {
+                    #sub_error::UnknownField(key) => #error_name::UnknownField(#alloc::format!("{}{}", #prefix, key)),
+                    #sub_error::Parse { field, message } => #error_name::Parse {
+                        field: #alloc::format!("{}{}", #prefix, field),
+                        message,
+                    },
+                    e => #error_name::SubBuilder {
+                        field: #lit,
+                        message: #alloc::string::ToString::to_string(&e),
+                    },
+                })?;
+                return ::core::result::Result::Ok(self);
+            }
+        }
+    });
+
+    let (impl_generics, ty_generics, _) = generics.split_for_impl();
+    // only types using the type parameters need bounds, the others are
+    // checked where they are parsed
+    let mut from_str_generics = generics.clone();
+    for ty in parsed_tys.iter().filter(|ty| uses_type_param(ty, generics)) {
+        let where_clause = from_str_generics.make_where_clause();
+        where_clause.predicates.push(syn::parse_quote!(#ty: ::core::str::FromStr));
+        where_clause.predicates.push(syn::parse_quote!(<#ty as ::core::str::FromStr>::Err: ::core::fmt::Display));
+    }
+    let (_, _, from_str_where_clause) = from_str_generics.split_for_impl();
+
+    quote! {
+        impl #impl_generics #builder_name #ty_generics #from_str_where_clause {
+            /// Sets the field named `key` to `value` parsed with `FromStr`. The
+            /// fields of a sub builder are named with dotted keys like `server.port`.
+            #vis fn set_from_str(&mut self, key: &str, value: &str) -> ::core::result::Result<&mut Self, #error_name> {
+                #[diagnostic::on_unimplemented(
+                    message = "`{Self}` can not be parsed with `FromStr`",
+                    label = "this field can not be set from a string",
+                    note = "implement `FromStr` for it, or leave the field out with `#[builder(from_str = false)]`"
+                )]
+                trait __FromStrField: ::core::marker::Sized {
+                    fn __from_str(s: &str) -> ::core::result::Result<Self, #alloc::string::String>;
+                }
+                impl<T: ::core::str::FromStr> __FromStrField for T
+                where
+                    T::Err: ::core::fmt::Display,
+                {
+                    fn __from_str(s: &str) -> ::core::result::Result<Self, #alloc::string::String> {
+                        <T as ::core::str::FromStr>::from_str(s).map_err(|e| #alloc::string::ToString::to_string(&e))
+                    }
+                }
+
+                match key {
+                    #(#arms)*
+                    _ => {
+                        #(#sub_builders)*
+                        return ::core::result::Result::Err(#error_name::UnknownField(#alloc::string::ToString::to_string(key)));
+                    }
+                }
+                ::core::result::Result::Ok(self)
+            }
+
+            /// Returns a builder with every `(key, value)` pair of `pairs` set
+            /// by [`set_from_str`](Self::set_from_str).
+            #vis fn from_pairs<I, K, V>(pairs: I) -> ::core::result::Result<Self, #error_name>
+            where
+                I: ::core::iter::IntoIterator<Item = (K, V)>,
+                K: ::core::convert::AsRef<str>,
+                V: ::core::convert::AsRef<str>,
+            {
+                let mut builder: Self = ::core::default::Default::default();
+                for (key, value) in pairs {
+                    builder.set_from_str(key.as_ref(), value.as_ref())?;
+                }
+                ::core::result::Result::Ok(builder)
+            }
+        }
+    }
+}
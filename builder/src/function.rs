@@ -0,0 +1,221 @@
+// Code generation for `#[builder]` on a function.
+//
+// The parameters of the function become the fields of a struct named after
+// it, `connect` gets `ConnectArgsValues`, and its builder `ConnectArgs` is
+// generated just like `#[derive(Builder)]` would. On top of the usual methods
+// the builder has `call()`, which builds the arguments and calls the
+// function with them. Builder attributes on the parameters are those of the
+// fields, the arguments of the macro those of the struct.
+
+use proc_macro2::{Span, TokenStream};
+use quote::{quote, quote_spanned};
+use syn::{parse_quote, Attribute, DeriveInput, FnArg, GenericArgument, Ident, ItemFn, Lifetime, LitStr, Pat, PathArguments, ReturnType, Type};
+
+use crate::attr::parse_struct_attr;
+use crate::derive_impl;
+
+// `connect_to_db` -> `ConnectToDb`
+fn camel_case(ident: &Ident) -> String {
+    ident
+        .to_string()
+        .split('_')
+        .filter(|part| !part.is_empty())
+        .map(|part| {
+            let mut chars = part.chars();
+            let first = chars.next().unwrap();
+            first.to_uppercase().chain(chars).collect::<String>()
+        })
+        .collect()
+}
+
+// Gives every elided lifetime in `ty` the name `lifetime`, since the fields of
+// a struct can not elide them. Returns whether any was found.
+fn name_elided_lifetimes(ty: &mut Type, lifetime: &Lifetime) -> bool {
+    match ty {
+        Type::Reference(r) => {
+            let elided = match &r.lifetime {
+                None => true,
+                Some(l) => l.ident == "_",
+            };
+            if elided {
+                r.lifetime = Some(lifetime.clone());
+            }
+            name_elided_lifetimes(&mut r.elem, lifetime) | elided
+        }
+        Type::Path(p) => {
+            let mut found = false;
+            for seg in &mut p.path.segments {
+                if let PathArguments::AngleBracketed(args) = &mut seg.arguments {
+                    for arg in &mut args.args {
+                        match arg {
+                            GenericArgument::Type(ty) => found |= name_elided_lifetimes(ty, lifetime),
+                            GenericArgument::Lifetime(l) if l.ident == "_" => {
+                                *l = lifetime.clone();
+                                found = true;
+                            }
+                            _ => {}
+                        }
+                    }
+                }
+            }
+            found
+        }
+        Type::Tuple(t) => t.elems.iter_mut().fold(false, |found, ty| name_elided_lifetimes(ty, lifetime) | found),
+        Type::Slice(s) => name_elided_lifetimes(&mut s.elem, lifetime),
+        Type::Array(a) => name_elided_lifetimes(&mut a.elem, lifetime),
+        Type::Paren(p) => name_elided_lifetimes(&mut p.elem, lifetime),
+        Type::Group(g) => name_elided_lifetimes(&mut g.elem, lifetime),
+        _ => false,
+    }
+}
+
+fn is_builder_attr(attr: &Attribute) -> bool {
+    attr.path.is_ident("builder")
+}
+
+pub(crate) fn expand(args: TokenStream, mut item: ItemFn) -> syn::Result<TokenStream> {
+    let sig = &item.sig;
+    let fn_name = sig.ident.clone();
+    if let Some(variadic) = &sig.variadic {
+        return Err(syn::Error::new_spanned(variadic, "`#[builder]` does not support variadic functions"));
+    }
+
+    let lifetime = Lifetime::new("'__args", Span::call_site());
+    let mut needs_lifetime = false;
+    let mut fields = vec![];
+    let mut names = vec![];
+    let mut tys = vec![];
+    let mut docs = vec![];
+    for input in &sig.inputs {
+        let pat_ty = match input {
+            FnArg::Typed(pat_ty) => pat_ty,
+            FnArg::Receiver(r) => return Err(syn::Error::new_spanned(r, "`#[builder]` does not support methods")),
+        };
+        let name = match &*pat_ty.pat {
+            Pat::Ident(p) if p.subpat.is_none() => &p.ident,
+            pat => return Err(syn::Error::new_spanned(pat, "`#[builder]` expects every parameter to be a plain name")),
+        };
+        if let Type::ImplTrait(_) = &*pat_ty.ty {
+            return Err(syn::Error::new_spanned(&pat_ty.ty, "`#[builder]` does not support `impl Trait` parameters"));
+        }
+        let mut ty = (*pat_ty.ty).clone();
+        needs_lifetime |= name_elided_lifetimes(&mut ty, &lifetime);
+        let attrs = pat_ty.attrs.iter().filter(|a| is_builder_attr(a));
+        let doc = format!("The `{}` argument of [`{}`].", name, fn_name);
+        // spanned at the parameter, so errors about the field point there
+        let doc_attr = quote_spanned!(name.span()=> #[doc = #doc]);
+        fields.push(quote!(#doc_attr #(#attrs)* #name: #ty));
+        docs.push(doc);
+        names.push(name.clone());
+        tys.push(ty);
+    }
+
+    let mut generics = sig.generics.clone();
+    if needs_lifetime {
+        generics.params.insert(0, parse_quote!(#lifetime));
+    }
+    let where_clause = &generics.where_clause;
+    // parameters only used by the return type still need to be used by the
+    // struct of the arguments
+    let params: Vec<TokenStream> = generics
+        .lifetimes()
+        .map(|l| {
+            let lifetime = &l.lifetime;
+            quote!(&#lifetime ())
+        })
+        .chain(generics.type_params().map(|t| {
+            let ident = &t.ident;
+            quote!(#ident)
+        }))
+        .collect();
+    let marker = if params.is_empty() {
+        None
+    } else {
+        Some(quote!(__marker: ::core::marker::PhantomData<fn() -> (#(#params, )*)>))
+    };
+    let skipped_marker = marker.iter();
+    let values_marker = marker.iter();
+
+    let vis = item.vis.clone();
+    let attr: Attribute = parse_quote!(#[builder(#args)]);
+    let mut attrs = if args.is_empty() { vec![] } else { vec![attr] };
+    let struct_attr = parse_struct_attr(&attrs)?;
+    if struct_attr.typestate {
+        return Err(syn::Error::new_spanned(&attrs[0], "`typestate` is not supported by `#[builder]` on functions"));
+    }
+    let builder_name = match &struct_attr.name {
+        Some(name) => name.clone(),
+        None => {
+            let name = Ident::new(&format!("{}Args", camel_case(&fn_name)), Span::call_site());
+            let lit = LitStr::new(&name.to_string(), Span::call_site());
+            attrs.push(parse_quote!(#[builder(name = #lit)]));
+            name
+        }
+    };
+    let values_name = Ident::new(&format!("{}Values", builder_name), Span::call_site());
+    let error_name = Ident::new(&format!("{}Error", builder_name), Span::call_site());
+
+    let input: DeriveInput = parse_quote! {
+        #(#attrs)*
+        #vis struct #values_name #generics #where_clause {
+            #(#fields, )*
+            #(#[builder(skip)] #skipped_marker, )*
+        }
+    };
+    let builder = derive_impl(input, true)?;
+
+    // the parameters keep their other attributes
+    for input in &mut item.sig.inputs {
+        if let FnArg::Typed(pat_ty) = input {
+            pat_ty.attrs.retain(|a| !is_builder_attr(a));
+        }
+    }
+    let sig = &item.sig;
+    let output = match &sig.output {
+        ReturnType::Default => quote!(()),
+        ReturnType::Type(_, ty) => quote!(#ty),
+    };
+    let receiver = struct_attr.pattern.unwrap_or_default().receiver();
+    let asyncness = &sig.asyncness;
+    let dot_await = asyncness.map(|_| quote!(.await));
+    // an `unsafe fn` makes `call` unsafe with the same contract
+    let unsafety = &sig.unsafety;
+    let call = quote!(#fn_name(#(args.#names),*)#dot_await);
+    let call = match unsafety {
+        Some(_) => quote!(unsafe { #call }),
+        None => call,
+    };
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let values_docs = format!("The arguments of [`{}`].", fn_name);
+    let call_docs = format!("Builds the arguments and calls [`{}`] with them.", fn_name);
+    let safety_docs = unsafety.map(|_| {
+        let safety = format!("The safety requirements are those of [`{}`].", fn_name);
+        quote! {
+            ///
+            /// # Safety
+            ///
+            #[doc = #safety]
+        }
+    });
+
+    Ok(quote! {
+        #item
+
+        #[doc = #values_docs]
+        #vis struct #values_name #generics #where_clause {
+            #(#[doc = #docs] #vis #names: #tys, )*
+            #(#values_marker, )*
+        }
+
+        #builder
+
+        impl #impl_generics #builder_name #ty_generics #where_clause {
+            #[doc = #call_docs]
+            #safety_docs
+            #vis #asyncness #unsafety fn call(#receiver) -> ::core::result::Result<#output, #error_name> {
+                let args = self.build()?;
+                ::core::result::Result::Ok(#call)
+            }
+        }
+    })
+}
@@ -1,13 +1,19 @@
 extern crate proc_macro;
 
+mod attr;
+mod from_str;
+mod function;
+mod typestate;
+
 use std::collections::HashMap;
 
 use proc_macro::TokenStream;
 use proc_macro2::Span;
 use quote::{quote, quote_spanned};
+use syn::{parse_macro_input, parse_quote, Attribute, Data, DeriveInput, ItemFn, Field, Fields, GenericArgument, Generics, Ident, Index, LitStr, Member, PathArguments, Type, Visibility};
 use syn::spanned::Spanned;
-use syn::{parse_macro_input, parse_quote, Data, DeriveInput, Field, Fields, GenericArgument, Ident, Lit, LitStr, Meta, NestedMeta, PathArguments, Type};
 
+use attr::{parse_field_attr, parse_struct_attr, resolved_local, FieldAttr, FieldDefault, StructAttr};
 
 fn is_option(ty: &Type) -> bool {
     if let Type::Path(ref p) = ty {
@@ -21,12 +27,8 @@ fn is_option(ty: &Type) -> bool {
 }
 
 fn wrap_option_ty(ty: Type) -> Type {
-    if is_option(&ty) {
-        ty
-    } else {
-        parse_quote! {
-            ::std::option::Option<#ty>
-        }
+    parse_quote! {
+        ::core::option::Option<#ty>
     }
 }
 
@@ -47,183 +49,1529 @@ fn extract_ty_from_option(ty: Type) -> Type {
     unreachable!()
 }
 
-fn extract_ty_from_vec(ty: Type) -> Type {
-    if let Type::Path(tp) = ty {
-        let seg = tp.path.segments.first().unwrap();
-        if let PathArguments::AngleBracketed(args) = &seg.arguments {
-            let generic_arg = args.args.first().unwrap();
-            if let GenericArgument::Type(new_ty) = generic_arg {
-                return new_ty.clone();
+// The items an `each` collection is extended with.
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum EachItem {
+    Single(Type),
+    // maps are extended with `(key, value)` pairs
+    Pair(Type, Type),
+}
+
+impl EachItem {
+    // An item type given with `each(item = "...")`, where a pair `(K, V)` is
+    // taken as the key and value of a map.
+    fn from_item(ty: Type) -> Self {
+        if let Type::Tuple(t) = &ty {
+            if t.elems.len() == 2 {
+                return EachItem::Pair(t.elems[0].clone(), t.elems[1].clone());
             }
         }
+        EachItem::Single(ty)
     }
-    unreachable!()
 
+    // The item type of `HashMap`, `BTreeMap` or a collection generic over
+    // just its items, `None` if it can not be told from the type.
+    fn infer(ty: &Type) -> Option<Self> {
+        let seg = match ty {
+            Type::Path(tp) => tp.path.segments.last().unwrap(),
+            _ => return None,
+        };
+        let args = match &seg.arguments {
+            PathArguments::AngleBracketed(args) => args,
+            _ => return None,
+        };
+        let tys: Vec<&Type> = args
+            .args
+            .iter()
+            .filter_map(|arg| match arg {
+                GenericArgument::Type(ty) => Some(ty),
+                _ => None,
+            })
+            .collect();
+        match tys[..] {
+            [k, v, ..] if seg.ident == "HashMap" || seg.ident == "BTreeMap" => Some(EachItem::Pair(k.clone(), v.clone())),
+            [item] => Some(EachItem::Single(item.clone())),
+            _ => None,
+        }
+    }
+}
+
+// What `extend_<each>` takes an iterator of.
+fn each_item_ty(attr: &FieldAttr) -> Type {
+    match attr.each_item.as_ref().unwrap() {
+        EachItem::Single(item) => item.clone(),
+        EachItem::Pair(k, v) => parse_quote!((#k, #v)),
+    }
+}
+
+// The bulk companions of an `each = "arg"` setter: `extend_arg` and
+// `clear_<field>`.
+fn each_companion_names(each: &Ident, name: &Ident) -> (Ident, Ident) {
+    (
+        Ident::new(&format!("extend_{}", each), each.span()),
+        Ident::new(&format!("clear_{}", name), name.span()),
+    )
+}
+
+// The one-at-a-time setter of an `each` field: any generic parameters it
+// needs, its arguments and the expression building the item to add.
+fn each_setter_args(attr: &FieldAttr, each: &Ident) -> (proc_macro2::TokenStream, proc_macro2::TokenStream, proc_macro2::TokenStream) {
+    match attr.each_item.as_ref().unwrap() {
+        EachItem::Single(item_ty) => {
+            let (generics, arg_ty, value) = setter_arg(attr, each, item_ty);
+            (generics, quote!(#each: #arg_ty), value)
+        }
+        EachItem::Pair(k, v) if attr.setter_into => (
+            quote!(<__Key: ::core::convert::Into<#k>, __Value: ::core::convert::Into<#v>>),
+            quote!(key: __Key, value: __Value),
+            quote!((::core::convert::Into::into(key), ::core::convert::Into::into(value))),
+        ),
+        EachItem::Pair(k, v) => (quote!(), quote!(key: #k, value: #v), quote!((key, value))),
+    }
+}
+
+// The builder of a `sub_builder` field's type, named like the builders this
+// macro generates.
+fn sub_builder_ty(ty: &Type) -> Type {
+    let mut ty = ty.clone();
+    if let Type::Path(tp) = &mut ty {
+        let seg = tp.path.segments.last_mut().unwrap();
+        seg.ident = Ident::new(&format!("{}Builder", seg.ident), seg.ident.span());
+    }
+    ty
 }
 
-fn wrap_option_field(mut f: Field) -> Field {
-    let ty = wrap_option_ty(f.ty);
-    f.ty = ty;
-    f.attrs = vec![];
-    f
+// The error type of a `sub_builder` field's builder.
+fn sub_error_ty(ty: &Type) -> Type {
+    let mut ty = sub_builder_ty(ty);
+    if let Type::Path(tp) = &mut ty {
+        let seg = tp.path.segments.last_mut().unwrap();
+        seg.ident = Ident::new(&format!("{}Error", seg.ident), seg.ident.span());
+        seg.arguments = PathArguments::None;
+    }
+    ty
 }
 
-fn check_builder_attribute(f: &Field) -> Result<Option<String>, TokenStream> {
-    if f.attrs.is_empty() {
-        return Ok(None);
+// The name of a field's setter, `name` unless renamed by
+// `setter(name = "...")` or `setter(prefix = "...")`.
+fn setter_name(attr: &FieldAttr, name: &Ident) -> Ident {
+    match (&attr.setter_name, &attr.setter_prefix) {
+        (Some(setter), _) => setter.clone(),
+        (None, Some(prefix)) => Ident::new(&format!("{}{}", prefix, name), name.span()),
+        (None, None) => name.clone(),
     }
+}
 
-    assert!(f.attrs.len() == 1);
+// The `///` docs of a field, carried over to its setter and builder field.
+fn doc_attrs(f: &Field) -> Vec<&Attribute> {
+    f.attrs.iter().filter(|a| a.path.is_ident("doc")).collect()
+}
 
-    let attr = &f.attrs[0];
-    //assert!(format!("{:?}", attr.path) == "builder");
+// A `#[doc]` attribute with the given text.
+fn doc(text: String) -> proc_macro2::TokenStream {
+    quote!(#[doc = #text])
+}
 
-    let meta = attr.parse_meta().unwrap();
-    let span = meta.span();
-    if let Meta::List(l) = meta {
-        if l.nested.len() == 1 {
-            if let NestedMeta::Meta(Meta::NameValue(nv)) = l.nested.into_iter().next().unwrap() {
-                if let Lit::Str(lstr) = nv.lit {
-                    if let Some(ident) = nv.path.get_ident() {
-                        if ident == "each" {
-                            let val = lstr.value();
-                            return Ok(Some(val));
-                        }
+// The docs of a setter: those of its field, or `fallback` if it has none.
+fn setter_docs(f: &Field, fallback: String) -> proc_macro2::TokenStream {
+    let docs = doc_attrs(f);
+    if docs.is_empty() {
+        doc(fallback)
+    } else {
+        quote!(#(#docs)*)
+    }
+}
+
+// The docs of `extend_<each>` and `clear_<field>`.
+fn each_companion_docs(name: &Ident) -> (proc_macro2::TokenStream, proc_macro2::TokenStream) {
+    (
+        doc(format!("Adds every item of `items` to `{}`.", name)),
+        doc(format!("Removes every item added to `{}`.", name)),
+    )
+}
+
+// The docs of `try_<setter>`.
+fn try_setter_docs(name: &Ident) -> proc_macro2::TokenStream {
+    doc(format!("Sets `{}` to a value converted with `TryInto`, returning the error of a failed conversion.", name))
+}
+
+// How the docs of generated items link to the type a builder builds.
+fn type_link(target: &BuilderTarget) -> String {
+    format!("[`{}`]", target.path.to_string().replace(' ', ""))
+}
+
+// Docs for a builder, listing its required and optional fields.
+fn builder_docs(target: &BuilderTarget) -> proc_macro2::TokenStream {
+    let BuilderTarget { fields, attrs, .. } = target;
+    let mut required = vec![];
+    let mut optional = vec![];
+    for f in fields {
+        let name = f.ident.as_ref().unwrap();
+        let attr = &attrs[name];
+        let env = attr.env.as_ref().map(|var| format!("read from `${}` when not set", var.value()));
+        let note = if attr.sub_builder {
+            Some("built by a nested builder".to_owned())
+        } else if attr.each.is_some() {
+            Some("empty unless items are added".to_owned())
+        } else if let Some(default) = &attr.default {
+            Some(default.describe())
+        } else if is_option(&f.ty) {
+            Some("defaults to `None`".to_owned())
+        } else {
+            None
+        };
+        match (note, env) {
+            (Some(note), Some(env)) => optional.push(format!("- `{}`: {}, otherwise {}", name, env, note)),
+            (Some(note), None) => optional.push(format!("- `{}`: {}", name, note)),
+            (None, Some(env)) => required.push(format!("- `{}`: {}", name, env)),
+            (None, None) => required.push(format!("- `{}`", name)),
+        }
+    }
+
+    let mut lines = vec![format!("Builder for {}.", type_link(target))];
+    for (title, fields) in [("Required fields:", required), ("Optional fields:", optional)] {
+        if !fields.is_empty() {
+            lines.extend([String::new(), title.to_owned(), String::new()]);
+            lines.extend(fields);
+        }
+    }
+    quote!(#(#[doc = #lines])*)
+}
+
+// `try_port` for the setter `port`.
+fn try_setter_name(setter: &Ident) -> Ident {
+    Ident::new(&format!("try_{}", setter), setter.span())
+}
+
+// The setter argument for a value of type `ty`: any generic parameters the
+// setter needs, the argument type and the expression converting the argument
+// into `ty`.
+fn setter_arg(attr: &FieldAttr, arg: &Ident, ty: &Type) -> (proc_macro2::TokenStream, proc_macro2::TokenStream, proc_macro2::TokenStream) {
+    if attr.setter_into {
+        (
+            quote!(<__Value: ::core::convert::Into<#ty>>),
+            quote!(__Value),
+            quote!(::core::convert::Into::into(#arg)),
+        )
+    } else {
+        (quote!(), quote!(#ty), quote!(#arg))
+    }
+}
+
+// The type a field setter takes, i.e. the field type without its `Option`
+// unless `setter(strip_option = false)` was given.
+fn setter_ty(attr: &FieldAttr, ty: &Type) -> Type {
+    if attr.keep_option {
+        ty.clone()
+    } else {
+        extract_ty_from_option(ty.clone())
+    }
+}
+
+// Wraps the converted setter argument back into the field type.
+fn setter_value(attr: &FieldAttr, ty: &Type, value: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+    if is_option(ty) && !attr.keep_option {
+        quote!(::core::option::Option::Some(#value))
+    } else {
+        value
+    }
+}
+
+#[derive(Clone, Copy, Default)]
+enum Pattern {
+    // setters and `build` take `&mut self`, `build` empties the builder
+    #[default]
+    Mutable,
+    // setters and `build` take `self` by value
+    Owned,
+    // setters and `build` take `&self` and work on a clone of the builder
+    Immutable,
+}
+
+impl Pattern {
+    fn parse(lit: &LitStr) -> syn::Result<Self> {
+        match lit.value().as_str() {
+            "mutable" => Ok(Pattern::Mutable),
+            "owned" => Ok(Pattern::Owned),
+            "immutable" => Ok(Pattern::Immutable),
+            _ => Err(syn::Error::new_spanned(lit, r#"expected "mutable", "owned" or "immutable""#)),
+        }
+    }
+
+    fn receiver(self) -> proc_macro2::TokenStream {
+        match self {
+            Pattern::Mutable => quote!(&mut self),
+            Pattern::Owned => quote!(self),
+            Pattern::Immutable => quote!(&self),
+        }
+    }
+
+    fn setter_return(self) -> proc_macro2::TokenStream {
+        match self {
+            Pattern::Mutable => quote!(&mut Self),
+            Pattern::Owned | Pattern::Immutable => quote!(Self),
+        }
+    }
+
+    // Binds the builder a setter or `build` works on to `new`.
+    fn bind_new(self) -> proc_macro2::TokenStream {
+        match self {
+            Pattern::Mutable => quote!(let new = self;),
+            Pattern::Owned => quote!(let mut new = self;),
+            Pattern::Immutable => quote!(let mut new = ::core::clone::Clone::clone(self);),
+        }
+    }
+
+    // Binds the builder `build` moves the fields out of to `new`.
+    fn bind_build(self) -> proc_macro2::TokenStream {
+        match self {
+            Pattern::Mutable | Pattern::Owned => quote!(let new = self;),
+            Pattern::Immutable => quote!(let new = ::core::clone::Clone::clone(self);),
+        }
+    }
+
+    // Moves a field out of the builder bound by `bind_build`.
+    fn take(self, name: &Ident) -> proc_macro2::TokenStream {
+        match self {
+            Pattern::Mutable => quote!(new.#name.take()),
+            Pattern::Owned | Pattern::Immutable => quote!(new.#name),
+        }
+    }
+}
+
+// A struct, or one variant of an enum, to generate a builder for.
+struct BuilderTarget {
+    // the type `build` returns
+    ty_name: Ident,
+    generics: Generics,
+    // the struct or enum variant `build` constructs
+    path: proc_macro2::TokenStream,
+    // the associated function of `ty_name` returning a new builder
+    builder_fn: Ident,
+    builder_name: Ident,
+    error_name: Ident,
+    // of the builder, its methods and its error type
+    vis: Visibility,
+    // every field has an ident, fields of tuple structs and variants are
+    // named `_0`, `_1`, ... unless they have a `#[builder(name = "...")]`
+    fields: Vec<Field>,
+    // how `build` refers to each field of `path`
+    members: Vec<Member>,
+    // `#[builder(skip)]` fields, which `build` sets to their default
+    skipped: Vec<Member>,
+    // the fields `default_with` functions depend on and those functions'
+    // own fields, every field after its dependencies
+    resolve_order: Vec<Ident>,
+    attrs: HashMap<Ident, FieldAttr>,
+    struct_attr: StructAttr,
+    // only structs can be turned back into a builder, a value of an enum may
+    // be any of its variants
+    is_struct: bool,
+}
+
+impl BuilderTarget {
+    fn new(
+        ty_name: &Ident,
+        generics: &Generics,
+        path: proc_macro2::TokenStream,
+        builder_fn: Ident,
+        builder_name: Ident,
+        fields: Fields,
+        struct_attr: StructAttr,
+    ) -> syn::Result<Self> {
+        let error_name = Ident::new(&format!("{}Error", builder_name), Span::call_site());
+        let mut ret = BuilderTarget {
+            ty_name: ty_name.clone(),
+            generics: generics.clone(),
+            path,
+            builder_fn,
+            builder_name,
+            error_name,
+            vis: struct_attr.vis.clone().unwrap_or(Visibility::Inherited),
+            fields: vec![],
+            members: vec![],
+            skipped: vec![],
+            resolve_order: vec![],
+            attrs: HashMap::new(),
+            struct_attr,
+            is_struct: false,
+        };
+        // report the attribute errors of every field at once
+        let mut errors: Option<syn::Error> = None;
+        for (i, mut f) in fields.into_iter().enumerate() {
+            let no_std = ret.struct_attr.no_std;
+            // a sub builder is edited in place through `&mut`, and put back when
+            // the build fails, which only the mutable pattern supports
+            let owned = !ret.struct_attr.typestate && !matches!(ret.struct_attr.pattern.unwrap_or_default(), Pattern::Mutable);
+            let attr = parse_field_attr(&f).and_then(|attr| match &attr.env {
+                Some(env) if no_std => Err(syn::Error::new_spanned(env, "`env` is not supported by `no_std` builders")),
+                _ if attr.sub_builder && owned => Err(syn::Error::new_spanned(&f, "`sub_builder` needs the default mutable pattern")),
+                _ => Ok(attr),
+            });
+            let attr = match attr {
+                Ok(attr) => attr,
+                Err(e) => {
+                    match &mut errors {
+                        Some(errors) => errors.combine(e),
+                        None => errors = Some(e),
                     }
+                    continue;
                 }
+            };
+            let member = match &f.ident {
+                Some(ident) => Member::Named(ident.clone()),
+                None => Member::Unnamed(Index::from(i)),
+            };
+            if attr.skip {
+                ret.skipped.push(member);
+                continue;
             }
+            let name = match (&attr.name, &f.ident) {
+                (Some(name), _) => name.clone(),
+                (None, Some(ident)) => ident.clone(),
+                (None, None) => Ident::new(&format!("_{}", i), Span::call_site()),
+            };
+            f.ident = Some(name.clone());
+            ret.fields.push(f);
+            ret.members.push(member);
+            ret.attrs.insert(name, attr);
         }
+        if let Some(e) = errors {
+            return Err(e);
+        }
+
+        let mut order = vec![];
+        for f in &ret.fields {
+            let name = f.ident.as_ref().unwrap();
+            if ret.attrs[name].default.as_ref().is_some_and(|d| !d.depends_on().is_empty()) {
+                ret.visit_dependencies(name, &mut vec![], &mut order)?;
+            }
+        }
+        ret.resolve_order = order;
+        Ok(ret)
     }
-    let ret = quote_spanned! { span =>
-        compile_error!(r#"expected `builder(each = "...")`"#);
-    };
-    Err(ret.into())
+
+    // Appends `name` to `order` after everything it depends on, `path` being
+    // the fields that led here.
+    fn visit_dependencies(&self, name: &Ident, path: &mut Vec<Ident>, order: &mut Vec<Ident>) -> syn::Result<()> {
+        if order.contains(name) {
+            return Ok(());
+        }
+        path.push(name.clone());
+        let depends_on = self.attrs[name].default.as_ref().map_or(&[][..], FieldDefault::depends_on);
+        for dep in depends_on {
+            if !self.attrs.contains_key(dep) {
+                return Err(syn::Error::new_spanned(dep, format!("unknown field `{}`", dep)));
+            }
+            if let Some(i) = path.iter().position(|n| n == dep) {
+                let cycle: Vec<String> = path[i..].iter().chain(Some(dep)).map(|n| n.to_string()).collect();
+                let msg = format!("`default_with` dependencies form a cycle: {}", cycle.join(" -> "));
+                return Err(syn::Error::new_spanned(dep, msg));
+            }
+            self.visit_dependencies(dep, path, order)?;
+        }
+        path.pop();
+        order.push(name.clone());
+        Ok(())
+    }
+}
+
+// `ShapeKind` -> `shape_kind`
+fn snake_case(ident: &Ident) -> String {
+    let s = ident.to_string();
+    let chars: Vec<char> = s.chars().collect();
+    let mut ret = String::new();
+    for (i, c) in chars.iter().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            let prev_lower = chars[i - 1].is_lowercase() || chars[i - 1].is_numeric();
+            let next_lower = chars.get(i + 1).is_some_and(|c| c.is_lowercase());
+            if prev_lower || (chars[i - 1].is_uppercase() && next_lower) {
+                ret.push('_');
+            }
+        }
+        ret.extend(c.to_lowercase());
+    }
+    ret
 }
 
 #[proc_macro_derive(Builder, attributes(builder))]
 pub fn derive(input: TokenStream) -> TokenStream {
     let input = parse_macro_input!(input as DeriveInput);
 
-    let struct_name = input.ident;
-    let builder_name = Ident::new(&format!("{}Builder", struct_name), Span::call_site());
+    match derive_impl(input, false) {
+        Ok(d) => d.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+// `#[builder]` on a function generates a builder for its arguments, see
+// function.rs.
+#[proc_macro_attribute]
+pub fn builder(args: TokenStream, input: TokenStream) -> TokenStream {
+    let item = parse_macro_input!(input as ItemFn);
+
+    match function::expand(args.into(), item) {
+        Ok(d) => d.into(),
+        Err(e) => e.to_compile_error().into(),
+    }
+}
+
+fn derive_impl(input: DeriveInput, call: bool) -> syn::Result<proc_macro2::TokenStream> {
+    let mut struct_attr = parse_struct_attr(&input.attrs)?;
+    struct_attr.call = call;
+    let DeriveInput { ident, vis, generics, data, .. } = input;
+    struct_attr.vis.get_or_insert(vis);
 
-    let fields = if let Data::Struct(s) = input.data {
-        if let Fields::Named(named) = s.fields {
-            named.named
+    match data {
+        Data::Struct(s) => {
+            let builder_name = match &struct_attr.name {
+                Some(name) => name.clone(),
+                None => Ident::new(&format!("{}Builder", ident), Span::call_site()),
+            };
+            let mut target = BuilderTarget::new(
+                &ident,
+                &generics,
+                quote!(#ident),
+                Ident::new("builder", Span::call_site()),
+                builder_name,
+                s.fields,
+                struct_attr,
+            )?;
+            target.is_struct = true;
+            Ok(expand(&target))
+        }
+        Data::Enum(e) => {
+            // one builder per variant, e.g. `Shape::circle_builder()` returning a
+            // `ShapeCircleBuilder` that builds `Shape::Circle`
+            if let Some(name) = &struct_attr.name {
+                return Err(syn::Error::new_spanned(name, "`name` is not supported on enums, which get one builder per variant"));
+            }
+            let mut ret = proc_macro2::TokenStream::new();
+            for variant in e.variants {
+                if let Some(attr) = variant.attrs.iter().find(|a| a.path.is_ident("builder")) {
+                    return Err(syn::Error::new_spanned(attr, "builder attributes are not supported on enum variants"));
+                }
+                let variant_name = &variant.ident;
+                let target = BuilderTarget::new(
+                    &ident,
+                    &generics,
+                    quote!(#ident::#variant_name),
+                    Ident::new(&format!("{}_builder", snake_case(variant_name)), Span::call_site()),
+                    Ident::new(&format!("{}{}Builder", ident, variant_name), Span::call_site()),
+                    variant.fields,
+                    struct_attr.clone(),
+                )?;
+                ret.extend(expand(&target));
+            }
+            Ok(ret)
+        }
+        Data::Union(u) => {
+            Err(syn::Error::new_spanned(u.union_token, "`Builder` can not be derived for unions"))
+        }
+    }
+}
+
+// Rejects fields whose generated methods would clash with the methods every
+// builder has, such as a setter named `status`.
+fn check_method_names(target: &BuilderTarget) -> syn::Result<()> {
+    let BuilderTarget { fields, attrs, struct_attr, .. } = target;
+    let mut builder_methods = vec!["build", "missing_fields", "merge", "reset_all", "status"];
+    if struct_attr.from_str {
+        builder_methods.extend(["set_from_str", "from_pairs"]);
+    }
+    if struct_attr.call {
+        builder_methods.push("call");
+    }
+
+    let mut errors: Option<syn::Error> = None;
+    for f in fields {
+        let name = f.ident.as_ref().unwrap();
+        let attr = &attrs[name];
+        let setter = setter_name(attr, name);
+        let field_method = |prefix: &str| Ident::new(&format!("{}{}", prefix, name), Span::call_site());
+        // every method generated for the field, with the attribute renaming it
+        let mut methods = vec![
+            (field_method("is_set_"), r#"name = "...""#),
+            (field_method("reset_"), r#"name = "...""#),
+        ];
+        if let Some(each) = &attr.each {
+            let (extend_name, clear_name) = each_companion_names(each, name);
+            methods.push((each.clone(), r#"each = "...""#));
+            methods.push((extend_name, r#"each = "...""#));
+            methods.push((clear_name, r#"name = "...""#));
+            if *each != setter {
+                methods.push((setter, r#"setter(name = "...")"#));
+            }
         } else {
-            panic!("only used to named fields")
+            if attr.try_setter {
+                methods.push((try_setter_name(&setter), r#"setter(name = "...")"#));
+            }
+            methods.push((setter, r#"setter(name = "...")"#));
         }
-    } else {
-        panic!("only used to struct")
-    };
 
-    // get builder attributes
-    let mut ty2attr: HashMap<Ident, String> = HashMap::new();
-    for f in fields.clone() {
-        match check_builder_attribute(&f) {
-            Ok(Some(d)) => {
-                ty2attr.insert(f.ident.clone().unwrap(), d);
+        for (method, rename) in methods {
+            if builder_methods.iter().any(|m| method == m) {
+                let msg = format!("the `{0}` method of this field clashes with the builder's own `{0}`, rename it with `#[builder({1})]`", method, rename);
+                let e = syn::Error::new_spanned(f, msg);
+                match &mut errors {
+                    Some(errors) => errors.combine(e),
+                    None => errors = Some(e),
+                }
             }
-            Ok(None) => {}
-            Err(e) => return e,
-        };
+        }
     }
+    match errors {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+fn expand(target: &BuilderTarget) -> proc_macro2::TokenStream {
+    if target.struct_attr.typestate {
+        return typestate::expand(target);
+    }
+    if let Err(e) = check_method_names(target) {
+        return e.to_compile_error();
+    }
+
+    let BuilderTarget {
+        ty_name: struct_name,
+        generics,
+        path,
+        builder_fn,
+        builder_name,
+        error_name,
+        vis,
+        fields,
+        members,
+        skipped,
+        resolve_order,
+        attrs: ty2attr,
+        struct_attr,
+        is_struct,
+    } = target;
 
     // generate builder fields
     let option_fields = fields.clone().into_iter().map(|mut f| {
-        let new_ty = if ty2attr.contains_key(f.ident.as_ref().unwrap()) {
+        let new_ty = if ty2attr[f.ident.as_ref().unwrap()].each.is_some() {
             f.ty.clone()
+        } else if ty2attr[f.ident.as_ref().unwrap()].sub_builder {
+            sub_builder_ty(&f.ty)
         } else {
             wrap_option_ty(f.ty.clone())
         };
-        f.attrs.clear();
+        f.attrs.retain(|a| a.path.is_ident("doc"));
         f.ty = new_ty;
         f
     });
+    // whether a setter was called on each `each` collection, which can not be
+    // told from the collection itself as it only needs to be `Extend`
+    let each_flags: Vec<Ident> = fields
+        .iter()
+        .map(|f| f.ident.as_ref().unwrap())
+        .filter(|name| ty2attr[*name].each.is_some())
+        .map(each_flag)
+        .collect();
 
 
+    let initial_values = fields.iter().map(|f| {
+        let name = f.ident.as_ref().unwrap();
+        if ty2attr[name].each.is_some() || ty2attr[name].sub_builder {
+            quote!(#name: ::core::default::Default::default())
+        } else {
+            quote!(#name: ::core::option::Option::None)
+        }
+    });
+
+    let alloc = struct_attr.alloc();
+    let docs = builder_docs(target);
+
     // generate builder setters
+    let pattern = struct_attr.pattern.unwrap_or_default();
+    let receiver = pattern.receiver();
+    let setter_return = pattern.setter_return();
+    let bind_new = pattern.bind_new();
+    let bind_build = pattern.bind_build();
     let setters = fields.clone().into_iter().map(|f| {
         let name = f.ident.clone().unwrap();
         let old_ty = f.ty.clone();
-        if let Some(attr_name) = ty2attr.get(f.ident.as_ref().unwrap()) {
-            let raw_ty = extract_ty_from_vec(f.ty.clone());
-            let new_name = Ident::new(attr_name, Span::call_site());
-            if attr_name == &name.to_string() {
-                quote! {
-                    pub fn #name(&mut self, #name: #raw_ty) -> &mut Self {
-                        self.#name.push(#name);
-                        self
-                    }
+        let attr = &ty2attr[f.ident.as_ref().unwrap()];
+        let setter = setter_name(attr, &name);
+        if attr.sub_builder {
+            let sub_ty = sub_builder_ty(&f.ty);
+            let docs = setter_docs(&f, format!("The builder of `{}`.", name));
+            quote! {
+                #docs
+                #vis fn #setter(&mut self) -> &mut #sub_ty {
+                    &mut self.#name
+                }
+            }
+        } else if let Some(new_name) = &attr.each {
+            let (item_generics, item_args, item_value) = each_setter_args(attr, new_name);
+            let item_ty = each_item_ty(attr);
+            let (extend_name, clear_name) = each_companion_names(new_name, &name);
+            let (extend_docs, clear_docs) = each_companion_docs(&name);
+            let docs = setter_docs(&f, format!("Adds an item to `{}`.", name));
+            let flag = each_flag(&name);
+            let each_setter = quote! {
+                #docs
+                #vis fn #new_name #item_generics(#receiver, #item_args) -> #setter_return {
+                    #bind_new
+                    ::core::iter::Extend::extend(&mut new.#name, ::core::iter::once(#item_value));
+                    new.#flag = true;
+                    new
+                }
+
+                #extend_docs
+                #vis fn #extend_name<__Items: ::core::iter::IntoIterator<Item = #item_ty>>(#receiver, items: __Items) -> #setter_return {
+                    #bind_new
+                    ::core::iter::Extend::extend(&mut new.#name, items);
+                    new.#flag = true;
+                    new
                 }
+
+                #clear_docs
+                #vis fn #clear_name(#receiver) -> #setter_return {
+                    #bind_new
+                    new.#name = ::core::default::Default::default();
+                    new.#flag = false;
+                    new
+                }
+            };
+            if *new_name == setter {
+                each_setter
             } else {
+                let (generics, arg_ty, value) = setter_arg(attr, &name, &old_ty);
+                let docs = setter_docs(&f, format!("Sets `{}`, replacing any items added before.", name));
                 quote! {
-                    pub fn #name(&mut self, #name: #old_ty) -> &mut Self {
-                        self.#name = #name;
-                        self
+                    #docs
+                    #vis fn #setter #generics(#receiver, #name: #arg_ty) -> #setter_return {
+                        #bind_new
+                        new.#name = #value;
+                        new.#flag = true;
+                        new
                     }
 
-                    pub fn #new_name(&mut self, #new_name: #raw_ty) -> &mut Self {
-                        self.#name.push(#new_name);
-                        self
-                    }
+                    #each_setter
                 }
             }
         } else {
-            let raw_ty = extract_ty_from_option(f.ty.clone());
+            let raw_ty = setter_ty(attr, &f.ty);
+            let (generics, arg_ty, value) = setter_arg(attr, &name, &raw_ty);
+            let value = setter_value(attr, &f.ty, value);
+            let try_setter = if attr.try_setter {
+                let try_name = try_setter_name(&setter);
+                let value = setter_value(attr, &f.ty, quote!(#name));
+                let docs = try_setter_docs(&name);
+                quote! {
+                    #docs
+                    #vis fn #try_name<__Value: ::core::convert::TryInto<#raw_ty>>(#receiver, #name: __Value) -> ::core::result::Result<#setter_return, __Value::Error> {
+                        let #name: #raw_ty = ::core::convert::TryInto::try_into(#name)?;
+                        #bind_new
+                        new.#name = ::core::option::Option::Some(#value);
+                        ::core::result::Result::Ok(new)
+                    }
+                }
+            } else {
+                quote!()
+            };
+            let docs = setter_docs(&f, format!("Sets `{}`.", name));
             quote! {
-                pub fn #name(&mut self, #name: #raw_ty) -> &mut Self {
-                    self.#name = ::std::option::Option::Some(#name);
-                    self
+                #docs
+                #vis fn #setter #generics(#receiver, #name: #arg_ty) -> #setter_return {
+                    #bind_new
+                    new.#name = ::core::option::Option::Some(#value);
+                    new
                 }
+
+                #try_setter
             }
         }
     });
 
     // generate builder constructor
-    let constructor = fields.clone().into_iter().map(|f| {
+    let resolve = |f: &Field| {
         let name = f.ident.clone().unwrap();
-        if ty2attr.contains_key(f.ident.as_ref().unwrap()) {
+        let attr = &ty2attr[f.ident.as_ref().unwrap()];
+        let value = pattern.take(&name);
+        if attr.sub_builder {
+            let local = resolved_local(&name);
+            quote!(#local)
+        } else if attr.each.is_some() {
+            match pattern {
+                Pattern::Mutable => {
+                    let flag = each_flag(&name);
+                    quote! {{
+                        new.#flag = false;
+                        ::core::mem::take(&mut new.#name)
+                    }}
+                }
+                Pattern::Owned | Pattern::Immutable => quote!(new.#name),
+            }
+        } else if attr.env.is_some() {
+            let local = Ident::new(&format!("__{}_env", name), Span::call_site());
+            let fallback = match &attr.default {
+                Some(default) => quote!(#default),
+                None if is_option(&f.ty) => quote!(::core::option::Option::None),
+                // `build` checked the variable it read before getting here
+                None => quote!(::core::unreachable!()),
+            };
             quote! {
-                #name: self.#name.clone()
+                match (#value, #local) {
+                    (::core::option::Option::Some(v), _) | (::core::option::Option::None, ::core::option::Option::Some(v)) => v,
+                    (::core::option::Option::None, ::core::option::Option::None) => #fallback,
+                }
             }
+        } else if let Some(default) = &attr.default {
+            quote! {
+                match #value {
+                    ::core::option::Option::Some(v) => v,
+                    ::core::option::Option::None => #default,
+                }
+            }
+        } else if is_option(&f.ty) {
+            quote!(#value.flatten())
         } else {
-            let err = LitStr::new(&format!("field {} not set", name), Span::call_site());
-            if is_option(&f.ty) {
+            // `build` checked for missing fields before getting here
+            quote! {
+                match #value {
+                    ::core::option::Option::Some(v) => v,
+                    ::core::option::Option::None => ::core::unreachable!(),
+                }
+            }
+        }
+    };
+
+    // with the mutable pattern a failing `build` puts back what it took, so
+    // the builder can be fixed and built again; fields only go back if they
+    // were set rather than defaulted
+    let set_flags: Vec<proc_macro2::TokenStream> = match pattern {
+        Pattern::Mutable => fields
+            .iter()
+            .map(|f| f.ident.as_ref().unwrap())
+            .filter(|name| !ty2attr[*name].sub_builder)
+            .map(|name| {
+                let flag = set_flag(name);
+                if ty2attr[name].each.is_some() {
+                    let each_flag = each_flag(name);
+                    quote!(let #flag = new.#each_flag;)
+                } else {
+                    quote!(let #flag = new.#name.is_some();)
+                }
+            })
+            .collect(),
+        Pattern::Owned | Pattern::Immutable => vec![],
+    };
+    // the hidden methods of a sub builder are called at the field's type, so
+    // a sub builder without them, not using the mutable pattern, fails there
+    let sub_span = |name: &Ident| fields.iter().find(|f| f.ident.as_ref() == Some(name)).unwrap().ty.span();
+    let restore = |names: &mut dyn Iterator<Item = &Ident>| match pattern {
+        Pattern::Mutable => {
+            let restores = names.map(|name| {
+                let local = resolved_local(name);
+                let attr = &ty2attr[name];
+                if attr.sub_builder {
+                    let flags = set_flag(name);
+                    quote_spanned!(sub_span(name)=> new.#name.__sub_builder_restore(#local, &mut ::core::iter::IntoIterator::into_iter(#flags));)
+                } else if attr.each.is_some() {
+                    let flag = set_flag(name);
+                    let each_flag = each_flag(name);
+                    quote! {
+                        new.#name = #local;
+                        new.#each_flag = #flag;
+                    }
+                } else {
+                    let flag = set_flag(name);
+                    quote! {
+                        if #flag {
+                            new.#name = ::core::option::Option::Some(#local);
+                        }
+                    }
+                }
+            });
+            quote!(#(#restores)*)
+        }
+        Pattern::Owned | Pattern::Immutable => quote!(),
+    };
+    let sub_fields: Vec<&Ident> = fields
+        .iter()
+        .map(|f| f.ident.as_ref().unwrap())
+        .filter(|name| ty2attr[*name].sub_builder)
+        .collect();
+
+    // every field is resolved into a local before the value is constructed:
+    // those `default_with` functions depend on first, in dependency order,
+    // followed by the remaining fields with a validator, each checked as soon
+    // as it is resolved, and then all others
+    let validated: Vec<&Ident> = fields
+        .iter()
+        .map(|f| f.ident.as_ref().unwrap())
+        .filter(|name| ty2attr[*name].validate.is_some() && !resolve_order.contains(name))
+        .collect();
+    let rest = fields
+        .iter()
+        .map(|f| f.ident.as_ref().unwrap())
+        .filter(|name| !resolve_order.contains(name) && !validated.contains(name));
+    let order: Vec<&Ident> = resolve_order.iter().chain(validated.iter().copied()).chain(rest).collect();
+    // a failing check puts back the sub builders and the fields resolved so far
+    let field_check = |i: usize| {
+        let name = order[i];
+        match &ty2attr[name].validate {
+            Some(path) => {
+                let local = resolved_local(name);
+                let lit = LitStr::new(&name.to_string(), Span::call_site());
+                let taken = order[..=i].iter().copied().filter(|name| !ty2attr[*name].sub_builder);
+                let restore = restore(&mut sub_fields.iter().copied().chain(taken));
                 quote! {
-                    #name: self.#name.take()
+                    if let ::core::result::Result::Err(e) = #path(&#local) {
+                        #restore
+                        return ::core::result::Result::Err(#error_name::InvalidField {
+                            field: #lit,
+                            message: ::core::convert::Into::into(e),
+                        });
+                    }
                 }
-            } else {
+            }
+            None => quote!(),
+        }
+    };
+    let resolved = order.iter().enumerate().map(|(i, name)| {
+        let check = field_check(i);
+        if ty2attr[name].sub_builder {
+            // already resolved with the other sub builders
+            return check;
+        }
+        let f = fields.iter().find(|f| f.ident.as_ref() == Some(name)).unwrap();
+        let local = resolved_local(name);
+        let value = resolve(f);
+        quote! {
+            let #local = #value;
+            #check
+        }
+    });
+    let constructor = fields.iter().zip(members).map(|(f, member)| {
+        let local = resolved_local(f.ident.as_ref().unwrap());
+        quote!(#member: #local)
+    });
+
+    let locals: Vec<Ident> = fields.iter().map(|f| resolved_local(f.ident.as_ref().unwrap())).collect();
+
+    // a mutable builder used as a sub builder reports which of its fields are
+    // set before it is built, so that the builder containing it can put back
+    // only those if it fails afterwards
+    let sub_builder_methods = match pattern {
+        Pattern::Mutable => {
+            let push_flags = fields.iter().map(|f| {
+                let name = f.ident.as_ref().unwrap();
+                let attr = &ty2attr[name];
+                if attr.sub_builder {
+                    quote_spanned!(sub_span(name)=> self.#name.__sub_builder_flags(flags);)
+                } else if attr.each.is_some() {
+                    let flag = each_flag(name);
+                    quote!(flags.push(self.#flag);)
+                } else {
+                    quote!(flags.push(self.#name.is_some());)
+                }
+            });
+            let restores = fields.iter().zip(&locals).map(|(f, local)| {
+                let name = f.ident.as_ref().unwrap();
+                let attr = &ty2attr[name];
+                if attr.sub_builder {
+                    quote_spanned!(sub_span(name)=> self.#name.__sub_builder_restore(#local, flags);)
+                } else if attr.each.is_some() {
+                    let flag = each_flag(name);
+                    quote! {
+                        self.#name = #local;
+                        self.#flag = flags.next() == ::core::option::Option::Some(true);
+                    }
+                } else {
+                    quote! {
+                        if flags.next() == ::core::option::Option::Some(true) {
+                            self.#name = ::core::option::Option::Some(#local);
+                        }
+                    }
+                }
+            });
+            let (_, ty_generics, _) = generics.split_for_impl();
+            quote! {
+                /// Appends whether each field is set to `flags`, for a builder
+                /// containing this one as a sub builder.
+                #[doc(hidden)]
+                #vis fn __sub_builder_flags(&self, flags: &mut #alloc::vec::Vec<bool>) {
+                    #(#push_flags)*
+                }
+
+                /// Puts the fields of `value` back that `flags` says were set,
+                /// undoing a `build` for a builder containing this one.
+                #[doc(hidden)]
+                #vis fn __sub_builder_restore<__Flags: ::core::iter::Iterator<Item = bool>>(&mut self, value: #struct_name #ty_generics, flags: &mut __Flags) {
+                    #[allow(unreachable_patterns)]
+                    match value {
+                        #path { #(#members: #locals, )* .. } => {
+                            #(#restores)*
+                        }
+                        _ => ::core::unreachable!(),
+                    }
+                }
+            }
+        }
+        Pattern::Owned | Pattern::Immutable => quote!(),
+    };
+    let restore_value = restore(&mut fields.iter().map(|f| f.ident.as_ref().unwrap()));
+    let restore_value = match pattern {
+        Pattern::Mutable => quote! {
+            #[allow(unreachable_patterns)]
+            match value {
+                #path { #(#members: #locals, )* .. } => {
+                    #restore_value
+                }
+                _ => ::core::unreachable!(),
+            }
+        },
+        Pattern::Owned | Pattern::Immutable => quote!(),
+    };
+
+    // sub builders are built up front, after checking that none of them
+    // misses a field; like the builder containing them they use the mutable
+    // pattern. When one fails for another reason, such as its validation,
+    // those built before it are put back from the values they built
+    let sub_missing_checks = fields.iter().filter(|f| ty2attr[f.ident.as_ref().unwrap()].sub_builder).map(|f| {
+        let name = f.ident.as_ref().unwrap();
+        let lit = LitStr::new(&name.to_string(), Span::call_site());
+        let sub_error = sub_error_ty(&f.ty);
+        quote! {
+            let missing = self.#name.missing_fields();
+            if !missing.is_empty() {
+                return ::core::result::Result::Err(#error_name::SubBuilder {
+                    field: #lit,
+                    message: #alloc::string::ToString::to_string(&#sub_error::MissingFields(missing)),
+                });
+            }
+        }
+    });
+    let sub_builds = sub_fields.iter().enumerate().map(|(i, name)| {
+        let local = resolved_local(name);
+        let lit = LitStr::new(&name.to_string(), Span::call_site());
+        let restore = restore(&mut sub_fields[..i].iter().copied());
+        let set_flags = match pattern {
+            Pattern::Mutable => {
+                let flags = set_flag(name);
+                let push_flags = quote_spanned!(sub_span(name)=> new.#name.__sub_builder_flags(&mut #flags));
                 quote! {
-                    #name: self.#name.take().ok_or(#err)?
+                    let mut #flags = #alloc::vec::Vec::new();
+                    #push_flags;
                 }
             }
+            Pattern::Owned | Pattern::Immutable => quote!(),
+        };
+        quote! {
+            #set_flags
+            let #local = match new.#name.build() {
+                ::core::result::Result::Ok(v) => v,
+                ::core::result::Result::Err(e) => {
+                    #restore
+                    return ::core::result::Result::Err(#error_name::SubBuilder {
+                        field: #lit,
+                        message: #alloc::string::ToString::to_string(&e),
+                    });
+                }
+            };
         }
+    });
 
+    // `missing_fields` looks at the environment itself, while `build` checks
+    // the variables it already read, so both agree even if one changes
+    let missing_checks = |in_build: bool| fields.iter().filter(|f| {
+        let attr = &ty2attr[f.ident.as_ref().unwrap()];
+        attr.each.is_none() && attr.default.is_none() && !attr.sub_builder && !is_option(&f.ty)
+    }).map(move |f| {
+        let name = f.ident.as_ref().unwrap();
+        let lit = LitStr::new(&name.to_string(), Span::call_site());
+        let unset = match &ty2attr[name].env {
+            Some(_) if in_build => {
+                let local = Ident::new(&format!("__{}_env", name), Span::call_site());
+                quote!(self.#name.is_none() && #local.is_none())
+            }
+            Some(var) => quote!(self.#name.is_none() && ::std::env::var_os(#var).is_none()),
+            None => quote!(self.#name.is_none()),
+        };
+        quote! {
+            if #unset {
+                missing.push(#lit);
+            }
+        }
     });
+    let build_missing_checks = missing_checks(true);
+    let missing_checks = missing_checks(false);
 
-    let ret = quote! {
-        impl #struct_name {
-            pub fn builder() -> #builder_name {
-                Default::default()
+    // environment variables are read and parsed once, before anything else,
+    // so a failure leaves this builder untouched
+    let env_reads = fields.iter().filter(|f| ty2attr[f.ident.as_ref().unwrap()].env.is_some()).map(|f| {
+        let name = f.ident.as_ref().unwrap();
+        let var = ty2attr[name].env.as_ref().unwrap();
+        let local = Ident::new(&format!("__{}_env", name), Span::call_site());
+        let lit = LitStr::new(&name.to_string(), Span::call_site());
+        let ty = extract_ty_from_option(f.ty.clone());
+        let value = if is_option(&f.ty) {
+            quote!(::core::option::Option::Some(v))
+        } else {
+            quote!(v)
+        };
+        let env_error = |message: proc_macro2::TokenStream| quote! {
+            #error_name::Env {
+                field: #lit,
+                var: #var,
+                message: #message,
             }
+        };
+        let parse_error = env_error(quote!(::std::string::ToString::to_string(&e)));
+        let unicode_error = env_error(quote!(::std::string::ToString::to_string(&::std::env::VarError::NotUnicode(s))));
+        quote! {
+            let #local = match self.#name {
+                ::core::option::Option::Some(_) => ::core::option::Option::None,
+                ::core::option::Option::None => match ::std::env::var(#var) {
+                    ::core::result::Result::Ok(s) => match <#ty as ::core::str::FromStr>::from_str(&s) {
+                        ::core::result::Result::Ok(v) => ::core::option::Option::Some(#value),
+                        ::core::result::Result::Err(e) => return ::core::result::Result::Err(#parse_error),
+                    },
+                    ::core::result::Result::Err(::std::env::VarError::NotPresent) => ::core::option::Option::None,
+                    ::core::result::Result::Err(::std::env::VarError::NotUnicode(s)) => return ::core::result::Result::Err(#unicode_error),
+                },
+            };
         }
+    });
 
-        #[derive(Default)]
-        pub struct #builder_name {
+    // later layers win and collections accumulate. Appending needs the
+    // collection to be iterable, which `each` does not ask for, so `merge`
+    // picks by autoref: the `__Append` impl applies to iterable collections
+    // and the `__Replace` one, reached through one less reference, to all
+    let merge_helpers = if each_flags.is_empty() {
+        quote!()
+    } else {
+        quote! {
+            struct __Merge<'__a, C>(&'__a mut C, ::core::option::Option<C>);
+            trait __Append {
+                fn __merge(self);
+            }
+            impl<'__a, '__b, '__c, C> __Append for &'__c mut &'__b mut __Merge<'__a, C>
+            where
+                C: ::core::iter::IntoIterator + ::core::iter::Extend<<C as ::core::iter::IntoIterator>::Item>,
+            {
+                fn __merge(self) {
+                    if let ::core::option::Option::Some(other) = self.1.take() {
+                        ::core::iter::Extend::extend(self.0, other);
+                    }
+                }
+            }
+            trait __Replace {
+                fn __merge(self);
+            }
+            impl<'__a, '__b, C> __Replace for &'__b mut __Merge<'__a, C> {
+                fn __merge(self) {
+                    if let ::core::option::Option::Some(other) = self.1.take() {
+                        *self.0 = other;
+                    }
+                }
+            }
+        }
+    };
+    let merges = fields.iter().map(|f| {
+        let name = f.ident.as_ref().unwrap();
+        let attr = &ty2attr[name];
+        if attr.sub_builder {
+            quote!(self.#name.merge(other.#name);)
+        } else if attr.each.is_some() {
+            let flag = each_flag(name);
+            quote! {
+                if other.#flag {
+                    (&mut &mut __Merge(&mut self.#name, ::core::option::Option::Some(other.#name))).__merge();
+                    self.#flag = true;
+                }
+            }
+        } else {
+            quote! {
+                if other.#name.is_some() {
+                    self.#name = other.#name;
+                }
+            }
+        }
+    });
+
+    // introspection: `is_set_<field>`, `reset_<field>` and `status`
+    let field_names: Vec<LitStr> = fields.iter().map(|f| LitStr::new(&f.ident.as_ref().unwrap().to_string(), Span::call_site())).collect();
+    let field_count = fields.len();
+    let required = fields.iter().map(|f| {
+        let attr = &ty2attr[f.ident.as_ref().unwrap()];
+        attr.each.is_none() && attr.default.is_none() && !attr.sub_builder && !is_option(&f.ty)
+    });
+    let is_set_names: Vec<Ident> = fields.iter().map(|f| Ident::new(&format!("is_set_{}", f.ident.as_ref().unwrap()), Span::call_site())).collect();
+    let introspection = fields.iter().zip(&is_set_names).map(|(f, is_set_name)| {
+        let name = f.ident.as_ref().unwrap();
+        let attr = &ty2attr[name];
+        let reset_name = Ident::new(&format!("reset_{}", name), Span::call_site());
+        let (is_set, reset) = if attr.sub_builder {
+            (
+                quote!(self.#name.status().iter().any(|(_, _, is_set)| *is_set)),
+                quote!(new.#name = ::core::default::Default::default();),
+            )
+        } else if attr.each.is_some() {
+            let flag = each_flag(name);
+            (
+                quote!(self.#flag),
+                quote! {
+                    new.#name = ::core::default::Default::default();
+                    new.#flag = false;
+                },
+            )
+        } else {
+            (quote!(self.#name.is_some()), quote!(new.#name = ::core::option::Option::None;))
+        };
+        let is_set_docs = doc(format!("Whether `{}` is set.", name));
+        let reset_docs = doc(format!("Unsets `{}`.", name));
+        quote! {
+            #is_set_docs
+            #vis fn #is_set_name(&self) -> bool {
+                #is_set
+            }
+
+            #reset_docs
+            #vis fn #reset_name(#receiver) -> #setter_return {
+                #bind_new
+                #reset
+                new
+            }
+        }
+    });
+    let reset_all = fields.iter().map(|f| {
+        let name = f.ident.as_ref().unwrap();
+        let attr = &ty2attr[name];
+        if attr.sub_builder {
+            quote!(new.#name = ::core::default::Default::default();)
+        } else if attr.each.is_some() {
+            let flag = each_flag(name);
+            quote! {
+                new.#name = ::core::default::Default::default();
+                new.#flag = false;
+            }
+        } else {
+            quote!(new.#name = ::core::option::Option::None;)
+        }
+    });
+
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    let builder_fn_docs = doc(format!("Returns a builder for {}.", type_link(target)));
+    let build_summary = format!("Builds the {}, failing if a required field is not set or a value is rejected.", type_link(target));
+    let build_docs = match pattern {
+        Pattern::Mutable => quote! {
+            #[doc = #build_summary]
+            ///
+            /// A successful build takes the values out of the builder, a failed one
+            /// leaves it as it was.
+        },
+        Pattern::Owned | Pattern::Immutable => quote!(#[doc = #build_summary]),
+    };
+
+    // the immutable pattern clones the builder in every setter and in `build`
+    let mut builder_generics = generics.clone();
+    let derive_clone = if let Pattern::Immutable = pattern {
+        builder_generics
+            .make_where_clause()
+            .predicates
+            .push(parse_quote!(#builder_name #ty_generics: ::core::clone::Clone));
+        quote!(#[derive(Clone)])
+    } else {
+        quote!()
+    };
+    let (_, _, builder_where_clause) = builder_generics.split_for_impl();
+
+    let validate = validate_call(struct_attr, error_name, restore_value);
+    let error_type = error_type(target);
+    let from_str = if struct_attr.from_str {
+        from_str::expand(target)
+    } else {
+        quote!()
+    };
+
+    let to_builder = if *is_struct {
+        let from_values = fields.iter().zip(members).map(|(f, member)| {
+            let name = f.ident.as_ref().unwrap();
+            let attr = &ty2attr[name];
+            if attr.sub_builder {
+                quote!(#name: ::core::convert::From::from(value.#member))
+            } else if attr.each.is_some() {
+                quote!(#name: value.#member)
+            } else {
+                quote!(#name: ::core::option::Option::Some(value.#member))
+            }
+        });
+        let to_builder = to_builder_fn(target, &quote!(#builder_name #ty_generics));
+        quote! {
+            impl #impl_generics ::core::convert::From<#struct_name #ty_generics> for #builder_name #ty_generics #where_clause {
+                fn from(value: #struct_name #ty_generics) -> Self {
+                    #builder_name {
+                        #(#from_values, )*
+                        #(#each_flags: true, )*
+                        __marker: ::core::marker::PhantomData,
+                    }
+                }
+            }
+
+            #to_builder
+        }
+    } else {
+        quote!()
+    };
+
+    quote! {
+        impl #impl_generics #struct_name #ty_generics #where_clause {
+            #builder_fn_docs
+            #vis fn #builder_fn() -> #builder_name #ty_generics {
+                ::core::default::Default::default()
+            }
+        }
+
+        #docs
+        #derive_clone
+        #vis struct #builder_name #generics #where_clause {
             #(#option_fields, )*
+            #(#each_flags: bool, )*
+            // enum variants may not use all generics of the enum
+            __marker: ::core::marker::PhantomData<fn() -> #struct_name #ty_generics>,
+        }
+
+        impl #impl_generics ::core::default::Default for #builder_name #ty_generics #where_clause {
+            fn default() -> Self {
+                #builder_name {
+                    #(#initial_values, )*
+                    #(#each_flags: false, )*
+                    __marker: ::core::marker::PhantomData,
+                }
+            }
         }
 
-        impl #builder_name {
+        impl #impl_generics #builder_name #ty_generics #builder_where_clause {
             #(#setters )*
 
-            pub fn build(&mut self) -> ::std::result::Result<#struct_name, ::std::boxed::Box<dyn ::std::error::Error>> {
-                Ok(#struct_name {
+            /// The required fields that are not set yet.
+            #vis fn missing_fields(&self) -> #alloc::vec::Vec<&'static str> {
+                let mut missing = #alloc::vec::Vec::new();
+                #(#missing_checks)*
+                missing
+            }
+
+            /// Sets every field that is set in `other`, adding the items of its
+            /// collections to those of this builder. Collections that can not be
+            /// iterated are replaced instead.
+            #vis fn merge(&mut self, other: Self) -> &mut Self {
+                #merge_helpers
+                #(#merges)*
+                self
+            }
+
+            #(#introspection)*
+
+            /// Unsets every field.
+            #vis fn reset_all(#receiver) -> #setter_return {
+                #bind_new
+                #(#reset_all)*
+                new
+            }
+
+            /// Every field as `(name, required, is_set)`.
+            #vis fn status(&self) -> [(&'static str, bool, bool); #field_count] {
+                [#((#field_names, #required, self.#is_set_names())),*]
+            }
+
+            #build_docs
+            #vis fn build(#receiver) -> ::core::result::Result<#struct_name #ty_generics, #error_name> {
+                #(#env_reads)*
+                let mut missing = #alloc::vec::Vec::new();
+                #(#build_missing_checks)*
+                if !missing.is_empty() {
+                    return ::core::result::Result::Err(#error_name::MissingFields(missing));
+                }
+                #(#sub_missing_checks)*
+                #bind_build
+                #(#set_flags)*
+                #(#sub_builds)*
+                #(#resolved)*
+                let value = #path {
                     #(#constructor, )*
+                    #(#skipped: ::core::default::Default::default(), )*
+                };
+                #validate
+                ::core::result::Result::Ok(value)
+            }
+
+            #sub_builder_methods
+        }
+
+        #to_builder
+
+        #from_str
+
+        #error_type
+    }
+}
+
+// `ty: bound` as a where-clause predicate that leaves the method carrying it
+// out when it does not hold, instead of failing to compile. A bound on a type
+// without generic parameters is checked where it is written, unless it is
+// wrapped in a `for<'__a>`, which defers it to the callers.
+fn optional_bound(ty: &Type, bound: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+    quote!(for<'__a> #ty: #bound)
+}
+
+// `to_builder`, cloning every field of the struct into a new builder. It is
+// only there when every field type is `Clone`.
+fn to_builder_fn(target: &BuilderTarget, builder_ty: &proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+    let BuilderTarget { ty_name, generics, path, vis, fields, members, skipped, .. } = target;
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+    let bounds = fields.iter().map(|f| optional_bound(&f.ty, quote!(::core::clone::Clone)));
+    let docs = doc(format!("Returns a builder with every field set to its value in this {}.", type_link(target)));
+    quote! {
+        impl #impl_generics #ty_name #ty_generics #where_clause {
+            #docs
+            #vis fn to_builder(&self) -> #builder_ty
+            where
+                #(#bounds, )*
+            {
+                ::core::convert::From::from(#path {
+                    #(#members: ::core::clone::Clone::clone(&self.#members), )*
+                    #(#skipped: ::core::default::Default::default(), )*
                 })
             }
         }
+    }
+}
+
+// Calls the `build_fn(validate = "...")` hook on the assembled `value`,
+// running `on_error` before returning its error.
+fn validate_call(struct_attr: &StructAttr, error_name: &Ident, on_error: proc_macro2::TokenStream) -> proc_macro2::TokenStream {
+    match &struct_attr.validate {
+        Some(path) => quote! {
+            if let ::core::result::Result::Err(e) = #path(&value) {
+                #on_error
+                return ::core::result::Result::Err(#error_name::Validation(::core::convert::Into::into(e)));
+            }
+        },
+        None => quote!(),
+    }
+}
+
+// The local a mutable `build` remembers whether `name` was set in.
+fn set_flag(name: &Ident) -> Ident {
+    Ident::new(&format!("set__{}", name), Span::call_site())
+}
+
+// The builder field recording whether the `each` collection `name` was set.
+fn each_flag(name: &Ident) -> Ident {
+    Ident::new(&format!("__is_set_{}", name), Span::call_site())
+}
+
+// The error type of `build`, with a variant for each way it can fail given the
+// features in use.
+fn error_type(target: &BuilderTarget) -> proc_macro2::TokenStream {
+    let BuilderTarget { builder_name, error_name, vis, fields, attrs, struct_attr, .. } = target;
+    let alloc = struct_attr.alloc();
+    let any_field = |f: fn(&FieldAttr) -> bool| fields.iter().any(|field| f(&attrs[field.ident.as_ref().unwrap()]));
+
+    let mut variants = vec![];
+    let mut arms = vec![];
+    // typestate builders check for missing fields at compile time
+    if !struct_attr.typestate {
+        variants.push(quote! {
+            /// Required fields that were not set.
+            MissingFields(#alloc::vec::Vec<&'static str>)
+        });
+        arms.push(quote!(#error_name::MissingFields(names) => ::core::write!(f, "fields not set: {}", names.join(", "))));
+    }
+    if struct_attr.validate.is_some() {
+        variants.push(quote! {
+            /// The `build_fn(validate = "...")` hook rejected the value.
+            Validation(#alloc::string::String)
+        });
+        arms.push(quote!(#error_name::Validation(msg) => ::core::write!(f, "validation failed: {}", msg)));
+    }
+    if any_field(|attr| attr.sub_builder) {
+        variants.push(quote! {
+            /// A nested builder failed to build.
+            SubBuilder {
+                /// The field built by the nested builder.
+                field: &'static str,
+                /// Why it failed.
+                message: #alloc::string::String,
+            }
+        });
+        arms.push(quote!(#error_name::SubBuilder { field, message } => ::core::write!(f, "failed to build {}: {}", field, message)));
+    }
+    if struct_attr.from_str {
+        variants.push(quote! {
+            /// `set_from_str` was given a key naming no field.
+            UnknownField(#alloc::string::String)
+        });
+        variants.push(quote! {
+            /// A value given to `set_from_str` failed to parse.
+            Parse {
+                /// The key the value was given for.
+                field: #alloc::string::String,
+                /// Why it failed to parse.
+                message: #alloc::string::String,
+            }
+        });
+        arms.push(quote!(#error_name::UnknownField(name) => ::core::write!(f, "unknown field `{}`", name)));
+        arms.push(quote!(#error_name::Parse { field, message } => ::core::write!(f, "failed to parse {}: {}", field, message)));
+    }
+    if any_field(|attr| attr.env.is_some()) {
+        variants.push(quote! {
+            /// An environment variable could not be read into a field.
+            Env {
+                /// The field read from the variable.
+                field: &'static str,
+                /// The environment variable.
+                var: &'static str,
+                /// Why it could not be read.
+                message: #alloc::string::String,
+            }
+        });
+        arms.push(quote!(#error_name::Env { field, var, message } => ::core::write!(f, "failed to read {} from ${}: {}", field, var, message)));
+    }
+    if any_field(|attr| attr.validate.is_some()) {
+        variants.push(quote! {
+            /// A field's `validate = "..."` function rejected its value.
+            InvalidField {
+                /// The rejected field.
+                field: &'static str,
+                /// The message of the validation function.
+                message: #alloc::string::String,
+            }
+        });
+        arms.push(quote!(#error_name::InvalidField { field, message } => ::core::write!(f, "invalid {}: {}", field, message)));
+    }
+
+    // `Error` lives in `std` only
+    let error_impl = if struct_attr.no_std {
+        quote!()
+    } else {
+        quote!(impl ::std::error::Error for #error_name {})
     };
+    let docs = doc(format!("The error of [`{}`].", builder_name));
+    quote! {
+        #docs
+        #[derive(Debug, Clone, PartialEq, Eq)]
+        #vis enum #error_name {
+            #(#variants, )*
+        }
 
-    ret.into()
+        impl ::core::fmt::Display for #error_name {
+            fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
+                match self {
+                    #(#arms, )*
+                }
+            }
+        }
+
+        #error_impl
+    }
 }
@@ -0,0 +1,369 @@
+// Code generation for `#[builder(typestate)]`.
+//
+// Every required field gets its own type parameter on the builder which is
+// `()` while the field is unset and `(T,)` once its setter has been called.
+// Setters of required fields are only implemented for the unset state and
+// `build` only for the state where every required field is set, so forgetting
+// a field becomes a compile error instead of a runtime error.
+
+use std::collections::HashMap;
+
+use proc_macro2::{Span, TokenStream};
+use quote::quote;
+use syn::{parse_quote, Field, GenericParam, Generics, Ident};
+
+use crate::{builder_docs, doc_attrs, each_companion_docs, each_companion_names, each_item_ty, each_setter_args, error_type, is_option, setter_arg, setter_name, setter_ty, setter_docs, setter_value, to_builder_fn, try_setter_docs, try_setter_name, type_link, validate_call, wrap_option_ty, BuilderTarget};
+
+// The struct's own generics followed by `states`, with defaults removed since
+// the state parameters come after them.
+fn generics_with_states(generics: &Generics, states: &[&Ident]) -> Generics {
+    let mut ret = generics.clone();
+    for param in &mut ret.params {
+        match param {
+            GenericParam::Type(t) => {
+                t.eq_token = None;
+                t.default = None;
+            }
+            GenericParam::Const(c) => {
+                c.eq_token = None;
+                c.default = None;
+            }
+            GenericParam::Lifetime(_) => {}
+        }
+    }
+    for state in states {
+        ret.params.push(parse_quote!(#state));
+    }
+    ret
+}
+
+// The argument list naming the struct's own generics followed by `states`.
+fn generic_args<I: IntoIterator<Item = TokenStream>>(generics: &Generics, states: I) -> TokenStream {
+    let params = generics.params.iter().map(|param| match param {
+        GenericParam::Type(t) => {
+            let ident = &t.ident;
+            quote!(#ident)
+        }
+        GenericParam::Lifetime(l) => {
+            let lifetime = &l.lifetime;
+            quote!(#lifetime)
+        }
+        GenericParam::Const(c) => {
+            let ident = &c.ident;
+            quote!(#ident)
+        }
+    });
+    let states = states.into_iter();
+    quote!(<#(#params, )* #(#states, )*>)
+}
+
+pub(crate) fn expand(target: &BuilderTarget) -> TokenStream {
+    let BuilderTarget {
+        ty_name: struct_name,
+        generics,
+        path,
+        builder_fn,
+        builder_name,
+        error_name,
+        vis,
+        fields,
+        members,
+        skipped,
+        resolve_order,
+        attrs,
+        struct_attr,
+        is_struct,
+    } = target;
+
+    if let Some(f) = fields.iter().find(|f| attrs[f.ident.as_ref().unwrap()].sub_builder) {
+        return syn::Error::new_spanned(f, "`sub_builder` is not supported by typestate builders").to_compile_error();
+    }
+    if let Some(f) = fields.iter().find(|f| attrs[f.ident.as_ref().unwrap()].env.is_some()) {
+        return syn::Error::new_spanned(f, "`env` is not supported by typestate builders").to_compile_error();
+    }
+    if let Some(f) = fields.iter().find(|f| attrs[f.ident.as_ref().unwrap()].validate.is_some()) {
+        return syn::Error::new_spanned(f, "`validate` on fields is not supported by typestate builders").to_compile_error();
+    }
+    if let Some(name) = resolve_order.first() {
+        return syn::Error::new_spanned(name, "`depends_on` is not supported by typestate builders").to_compile_error();
+    }
+
+    let names: Vec<&Ident> = fields.iter().map(|f| f.ident.as_ref().unwrap()).collect();
+    let is_required = |f: &Field| {
+        let attr = &attrs[f.ident.as_ref().unwrap()];
+        attr.each.is_none() && attr.default.is_none() && !is_option(&f.ty)
+    };
+
+    // one type parameter per required field
+    let states: HashMap<&Ident, Ident> = fields
+        .iter()
+        .filter(|f| is_required(f))
+        .enumerate()
+        .map(|(i, f)| (f.ident.as_ref().unwrap(), Ident::new(&format!("__Field{}", i), Span::call_site())))
+        .collect();
+    let all_states: Vec<&Ident> = names.iter().filter_map(|name| states.get(name)).collect();
+    let unset_args = generic_args(generics, all_states.iter().map(|_| quote!(())));
+    let set_args = generic_args(generics, fields.iter().filter(|f| is_required(f)).map(|f| {
+        let ty = &f.ty;
+        quote!((#ty,))
+    }));
+    let all_args = generic_args(generics, all_states.iter().map(|s| quote!(#s)));
+    let all_generics = generics_with_states(generics, &all_states);
+    let (all_impl_generics, _, _) = all_generics.split_for_impl();
+    let (impl_generics, ty_generics, where_clause) = generics.split_for_impl();
+
+    // generate builder fields
+    let builder_fields = fields.iter().map(|f| {
+        let name = f.ident.as_ref().unwrap();
+        let ty = if let Some(state) = states.get(name) {
+            quote!(#state)
+        } else if attrs[name].each.is_some() {
+            let ty = &f.ty;
+            quote!(#ty)
+        } else {
+            let ty = wrap_option_ty(f.ty.clone());
+            quote!(#ty)
+        };
+        let docs = doc_attrs(f);
+        quote!(#(#docs)* #name: #ty)
+    });
+
+    let initial_values = fields.iter().map(|f| {
+        let name = f.ident.as_ref().unwrap();
+        if states.contains_key(name) {
+            quote!(#name: ())
+        } else if attrs[name].each.is_some() {
+            quote!(#name: ::core::default::Default::default())
+        } else {
+            quote!(#name: ::core::option::Option::None)
+        }
+    });
+
+    let docs = builder_docs(target);
+
+    // generate builder setters
+    let setters = fields.iter().map(|f| {
+        let name = f.ident.as_ref().unwrap();
+        let setter = setter_name(&attrs[name], name);
+        let docs = setter_docs(f, format!("Sets `{}`.", name));
+        let try_docs = try_setter_docs(name);
+        let ty = &f.ty;
+        if let Some(state) = states.get(name) {
+            let other_states: Vec<&Ident> = all_states.iter().copied().filter(|s| s != &state).collect();
+            let other_generics = generics_with_states(generics, &other_states);
+            let (other_impl_generics, _, _) = other_generics.split_for_impl();
+            let before = generic_args(generics, all_states.iter().map(|s| if s == &state { quote!(()) } else { quote!(#s) }));
+            let after = generic_args(generics, all_states.iter().map(|s| if s == &state { quote!((#ty,)) } else { quote!(#s) }));
+            let moved = names.iter().filter(|n| *n != &name);
+            let (arg_generics, arg_ty, value) = setter_arg(&attrs[name], name, ty);
+            let try_setter = if attrs[name].try_setter {
+                let try_name = try_setter_name(&setter);
+                quote! {
+                    #try_docs
+                    #vis fn #try_name<__Value: ::core::convert::TryInto<#ty>>(self, #name: __Value) -> ::core::result::Result<#builder_name #after, __Value::Error> {
+                        let #name: #ty = ::core::convert::TryInto::try_into(#name)?;
+                        ::core::result::Result::Ok(self.#setter(#name))
+                    }
+                }
+            } else {
+                quote!()
+            };
+            quote! {
+                impl #other_impl_generics #builder_name #before #where_clause {
+                    #docs
+                    #vis fn #setter #arg_generics(self, #name: #arg_ty) -> #builder_name #after {
+                        #builder_name {
+                            #name: (#value,),
+                            #(#moved: self.#moved, )*
+                            __marker: ::core::marker::PhantomData,
+                        }
+                    }
+
+                    #try_setter
+                }
+            }
+        } else if let Some(each) = &attrs[name].each {
+            let (item_generics, item_args, item_value) = each_setter_args(&attrs[name], each);
+            let item_ty = each_item_ty(&attrs[name]);
+            let (extend_name, clear_name) = each_companion_names(each, name);
+            let (extend_docs, clear_docs) = each_companion_docs(name);
+            let each_docs = setter_docs(f, format!("Adds an item to `{}`.", name));
+            let all_at_once = if *each != setter {
+                let (arg_generics, arg_ty, value) = setter_arg(&attrs[name], name, ty);
+                let docs = setter_docs(f, format!("Sets `{}`, replacing any items added before.", name));
+                quote! {
+                    #docs
+                    #vis fn #setter #arg_generics(mut self, #name: #arg_ty) -> Self {
+                        self.#name = #value;
+                        self
+                    }
+                }
+            } else {
+                quote!()
+            };
+            quote! {
+                impl #all_impl_generics #builder_name #all_args #where_clause {
+                    #all_at_once
+
+                    #each_docs
+                    #vis fn #each #item_generics(mut self, #item_args) -> Self {
+                        ::core::iter::Extend::extend(&mut self.#name, ::core::iter::once(#item_value));
+                        self
+                    }
+
+                    #extend_docs
+                    #vis fn #extend_name<__Items: ::core::iter::IntoIterator<Item = #item_ty>>(mut self, items: __Items) -> Self {
+                        ::core::iter::Extend::extend(&mut self.#name, items);
+                        self
+                    }
+
+                    #clear_docs
+                    #vis fn #clear_name(mut self) -> Self {
+                        self.#name = ::core::default::Default::default();
+                        self
+                    }
+                }
+            }
+        } else {
+            let raw_ty = setter_ty(&attrs[name], ty);
+            let (arg_generics, arg_ty, value) = setter_arg(&attrs[name], name, &raw_ty);
+            let value = setter_value(&attrs[name], ty, value);
+            let try_setter = if attrs[name].try_setter {
+                let try_name = try_setter_name(&setter);
+                let value = setter_value(&attrs[name], ty, quote!(#name));
+                quote! {
+                    #try_docs
+                    #vis fn #try_name<__Value: ::core::convert::TryInto<#raw_ty>>(mut self, #name: __Value) -> ::core::result::Result<Self, __Value::Error> {
+                        let #name: #raw_ty = ::core::convert::TryInto::try_into(#name)?;
+                        self.#name = ::core::option::Option::Some(#value);
+                        ::core::result::Result::Ok(self)
+                    }
+                }
+            } else {
+                quote!()
+            };
+            quote! {
+                impl #all_impl_generics #builder_name #all_args #where_clause {
+                    #docs
+                    #vis fn #setter #arg_generics(mut self, #name: #arg_ty) -> Self {
+                        self.#name = ::core::option::Option::Some(#value);
+                        self
+                    }
+
+                    #try_setter
+                }
+            }
+        }
+    });
+
+    // generate builder constructor
+    let constructor = fields.iter().zip(members).map(|(f, member)| {
+        let name = f.ident.as_ref().unwrap();
+        let attr = &attrs[name];
+        if states.contains_key(name) {
+            quote!(#member: self.#name.0)
+        } else if let Some(default) = &attr.default {
+            quote! {
+                #member: match self.#name {
+                    ::core::option::Option::Some(v) => v,
+                    ::core::option::Option::None => #default,
+                }
+            }
+        } else if attr.each.is_some() {
+            quote!(#member: self.#name)
+        } else {
+            quote!(#member: self.#name.flatten())
+        }
+    });
+
+    // with a validation hook `build` can still fail, so it needs an error type
+    let build = if struct_attr.validate.is_some() {
+        let build_docs = format!("Builds the {}, failing if it is rejected by the validation hook.", type_link(target));
+        let validate = validate_call(struct_attr, error_name, quote!());
+        let error_type = error_type(target);
+        quote! {
+            impl #impl_generics #builder_name #set_args #where_clause {
+                #[doc = #build_docs]
+                #vis fn build(self) -> ::core::result::Result<#struct_name #ty_generics, #error_name> {
+                    let value = #path {
+                        #(#constructor, )*
+                        #(#skipped: ::core::default::Default::default(), )*
+                    };
+                    #validate
+                    ::core::result::Result::Ok(value)
+                }
+            }
+
+            #error_type
+        }
+    } else {
+        let build_docs = format!("Builds the {}.", type_link(target));
+        quote! {
+            impl #impl_generics #builder_name #set_args #where_clause {
+                #[doc = #build_docs]
+                #vis fn build(self) -> #struct_name #ty_generics {
+                    #path {
+                        #(#constructor, )*
+                        #(#skipped: ::core::default::Default::default(), )*
+                    }
+                }
+            }
+        }
+    };
+
+    // a struct turns back into a builder with every required field set
+    let to_builder = if *is_struct {
+        let from_values = fields.iter().zip(members).map(|(f, member)| {
+            let name = f.ident.as_ref().unwrap();
+            if states.contains_key(name) {
+                quote!(#name: (value.#member,))
+            } else if attrs[name].each.is_some() {
+                quote!(#name: value.#member)
+            } else {
+                quote!(#name: ::core::option::Option::Some(value.#member))
+            }
+        });
+        let to_builder = to_builder_fn(target, &quote!(#builder_name #set_args));
+        quote! {
+            impl #impl_generics ::core::convert::From<#struct_name #ty_generics> for #builder_name #set_args #where_clause {
+                fn from(value: #struct_name #ty_generics) -> Self {
+                    #builder_name {
+                        #(#from_values, )*
+                        __marker: ::core::marker::PhantomData,
+                    }
+                }
+            }
+
+            #to_builder
+        }
+    } else {
+        quote!()
+    };
+
+    let builder_fn_docs = format!("Returns a builder for {}.", type_link(target));
+    quote! {
+        impl #impl_generics #struct_name #ty_generics #where_clause {
+            #[doc = #builder_fn_docs]
+            #vis fn #builder_fn() -> #builder_name #unset_args {
+                #builder_name {
+                    #(#initial_values, )*
+                    __marker: ::core::marker::PhantomData,
+                }
+            }
+        }
+
+        #docs
+        #vis struct #builder_name #all_generics #where_clause {
+            #(#builder_fields, )*
+            // required fields are stored in the state parameters, which may
+            // leave some of the struct's own generics otherwise unused
+            __marker: ::core::marker::PhantomData<fn() -> #struct_name #ty_generics>,
+        }
+
+        #(#setters)*
+
+        #build
+
+        #to_builder
+    }
+}
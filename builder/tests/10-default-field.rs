@@ -0,0 +1,49 @@
+// Fields marked #[builder(default)] fall back to Default::default() when their
+// setter is never called, and #[builder(default = "...")] evaluates the given
+// expression at build time instead. Such fields are no longer required.
+
+use derive_builder::Builder;
+
+fn default_retries() -> u32 {
+    3
+}
+
+#[derive(Builder)]
+pub struct Config {
+    host: String,
+    #[builder(default)]
+    port: u16,
+    #[builder(default = "default_retries()")]
+    retries: u32,
+    #[builder(default = "vec![\"a\".to_owned()]")]
+    tags: Vec<String>,
+    #[builder(default = "Some(8)")]
+    workers: Option<usize>,
+}
+
+fn main() {
+    let config = Config::builder()
+        .host("localhost".to_owned())
+        .build()
+        .unwrap();
+    assert_eq!(config.host, "localhost");
+    assert_eq!(config.port, 0);
+    assert_eq!(config.retries, 3);
+    assert_eq!(config.tags, vec!["a".to_owned()]);
+    assert_eq!(config.workers, Some(8));
+
+    let config = Config::builder()
+        .host("localhost".to_owned())
+        .port(8080)
+        .retries(5)
+        .tags(vec![])
+        .workers(2)
+        .build()
+        .unwrap();
+    assert_eq!(config.port, 8080);
+    assert_eq!(config.retries, 5);
+    assert!(config.tags.is_empty());
+    assert_eq!(config.workers, Some(2));
+
+    assert!(Config::builder().build().is_err());
+}
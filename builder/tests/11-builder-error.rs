@@ -0,0 +1,39 @@
+// The build method returns a dedicated error type named after the builder, so
+// callers can tell which field was missing instead of inspecting a string.
+//
+//     pub enum CommandBuilderError {
+//         MissingFields(Vec<&'static str>),
+//     }
+//
+// Other variants are only added for features in use that can make build() fail,
+// e.g. Validation(String) with #[builder(build_fn(validate = "..."))], so a
+// match only has to handle errors that can actually occur.
+//
+// The error implements std::error::Error and Display, so it still converts into
+// Box<dyn Error> with the ? operator.
+
+use derive_builder::Builder;
+use std::error::Error;
+
+#[derive(Builder)]
+pub struct Command {
+    executable: String,
+    current_dir: Option<String>,
+}
+
+fn build_boxed() -> Result<Command, Box<dyn Error>> {
+    let command = Command::builder().build()?;
+    Ok(command)
+}
+
+fn main() {
+    let err = Command::builder().build().err().unwrap();
+    assert_eq!(err, CommandBuilderError::MissingFields(vec!["executable"]));
+    assert_eq!(err.to_string(), "fields not set: executable");
+
+    match err {
+        CommandBuilderError::MissingFields(fields) => assert_eq!(fields, ["executable"]),
+    }
+
+    assert!(build_boxed().is_err());
+}
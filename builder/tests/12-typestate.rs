@@ -0,0 +1,34 @@
+// With #[builder(typestate)] the builder tracks in its type which required
+// fields have been set. Setters take and return the builder by value, and
+// build() only exists once every required field has been provided, so it can
+// return the struct directly instead of a Result.
+
+use derive_builder::Builder;
+
+#[derive(Builder)]
+#[builder(typestate)]
+pub struct Command {
+    executable: String,
+    #[builder(each = "arg")]
+    args: Vec<String>,
+    #[builder(default = "\"/\".to_owned()")]
+    current_dir: String,
+    timeout: Option<u64>,
+    retries: u32,
+}
+
+fn main() {
+    let command = Command::builder()
+        .arg("build".to_owned())
+        .retries(3)
+        .timeout(60)
+        .executable("cargo".to_owned())
+        .arg("--release".to_owned())
+        .build();
+
+    assert_eq!(command.executable, "cargo");
+    assert_eq!(command.args, vec!["build", "--release"]);
+    assert_eq!(command.current_dir, "/");
+    assert_eq!(command.timeout, Some(60));
+    assert_eq!(command.retries, 3);
+}
@@ -0,0 +1,64 @@
+// The builder carries over the generics of the struct it builds: lifetimes,
+// type parameters with bounds and defaults, const generics and where-clauses.
+// No extra bounds such as Default are required of the type parameters.
+
+use derive_builder::Builder;
+use std::fmt::Debug;
+
+pub trait Codec {
+    fn name(&self) -> &'static str;
+}
+
+pub struct Json;
+
+impl Codec for Json {
+    fn name(&self) -> &'static str {
+        "json"
+    }
+}
+
+#[derive(Builder)]
+pub struct Request<'a, T: Codec, U = u8, const N: usize = 4>
+where
+    U: Debug,
+{
+    path: &'a str,
+    codec: T,
+    #[builder(each = "tag")]
+    tags: Vec<String>,
+    extra: Option<U>,
+    checksum: Option<[u8; N]>,
+}
+
+#[derive(Builder)]
+#[builder(typestate)]
+pub struct Response<'a, T, const N: usize>
+where
+    T: Codec,
+{
+    body: &'a [u8; N],
+    codec: T,
+    status: Option<u16>,
+}
+
+fn main() {
+    let path = String::from("/index.html");
+    let request: Request<Json> = Request::builder()
+        .path(&path)
+        .codec(Json)
+        .tag("a".to_owned())
+        .tag("b".to_owned())
+        .build()
+        .unwrap();
+    assert_eq!(request.path, "/index.html");
+    assert_eq!(request.codec.name(), "json");
+    assert_eq!(request.tags, vec!["a", "b"]);
+    assert!(request.extra.is_none());
+    assert!(request.checksum.is_none());
+
+    let body = [1, 2, 3];
+    let response = Response::builder().codec(Json).body(&body).build();
+    assert_eq!(response.body.len(), 3);
+    assert_eq!(response.codec.name(), "json");
+    assert!(response.status.is_none());
+}
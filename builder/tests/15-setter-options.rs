@@ -0,0 +1,58 @@
+// #[builder(setter(into))] makes a setter generic over anything that converts
+// into the field type, so a String field accepts a &str. For `each` fields the
+// one-at-a-time setter converts the element as well.
+//
+// Setters of Option fields take the inner value by default.
+// #[builder(setter(strip_option = false))] keeps the Option in the setter's
+// signature so that a caller can explicitly set the field to None, which then
+// also takes precedence over any default.
+
+use derive_builder::Builder;
+
+#[derive(Builder)]
+pub struct Command {
+    #[builder(setter(into))]
+    executable: String,
+    #[builder(each = "arg", setter(into))]
+    args: Vec<String>,
+    #[builder(setter(into, strip_option = false))]
+    current_dir: Option<String>,
+    #[builder(setter(strip_option = false), default = "Some(30)")]
+    timeout: Option<u64>,
+}
+
+#[derive(Builder)]
+#[builder(typestate)]
+pub struct Request {
+    #[builder(setter(into))]
+    path: String,
+    #[builder(setter(into))]
+    method: Option<String>,
+}
+
+fn main() {
+    let command = Command::builder()
+        .executable("cargo")
+        .arg("build")
+        .arg(String::from("--release"))
+        .current_dir(Some("..".to_owned()))
+        .build()
+        .unwrap();
+    assert_eq!(command.executable, "cargo");
+    assert_eq!(command.args, vec!["build", "--release"]);
+    assert_eq!(command.current_dir.as_deref(), Some(".."));
+    assert_eq!(command.timeout, Some(30));
+
+    let command = Command::builder()
+        .executable("cargo")
+        .current_dir(None)
+        .timeout(None)
+        .build()
+        .unwrap();
+    assert!(command.current_dir.is_none());
+    assert!(command.timeout.is_none());
+
+    let request = Request::builder().method("GET").path("/").build();
+    assert_eq!(request.path, "/");
+    assert_eq!(request.method.as_deref(), Some("GET"));
+}
@@ -0,0 +1,51 @@
+// By default setters and build() take &mut self and build() moves the values
+// out of the builder. #[builder(pattern = "...")] selects a different style:
+//
+//   - "owned": setters take and return the builder by value and build()
+//     consumes it.
+//   - "immutable": setters take &self and return a modified copy, and build()
+//     takes &self, so one builder can be used as a template for many values.
+
+use derive_builder::Builder;
+
+#[derive(Builder)]
+#[builder(pattern = "owned")]
+pub struct Command {
+    executable: String,
+    #[builder(each = "arg")]
+    args: Vec<String>,
+    current_dir: Option<String>,
+}
+
+#[derive(Builder)]
+#[builder(pattern = "immutable")]
+pub struct Request<T> {
+    path: String,
+    body: T,
+    #[builder(default)]
+    retries: u32,
+}
+
+fn main() {
+    let builder = Command::builder().executable("cargo".to_owned());
+    let builder = builder.arg("build".to_owned());
+    let command = builder.current_dir("..".to_owned()).build().unwrap();
+    assert_eq!(command.executable, "cargo");
+    assert_eq!(command.args, vec!["build"]);
+    assert_eq!(command.current_dir.as_deref(), Some(".."));
+
+    let template = Request::builder().path("/".to_owned());
+    let first = template.body(1).build().unwrap();
+    let second = template.body(2).retries(3).build().unwrap();
+    assert_eq!(first.path, "/");
+    assert_eq!(first.body, 1);
+    assert_eq!(first.retries, 0);
+    assert_eq!(second.body, 2);
+    assert_eq!(second.retries, 3);
+
+    let builder = template.body(3);
+    let third = builder.build().unwrap();
+    let fourth = builder.build().unwrap();
+    assert_eq!(third.body, fourth.body);
+    assert!(template.build().is_err());
+}
@@ -0,0 +1,68 @@
+// #[builder(build_fn(validate = "path"))] names a function that build() calls
+// with the assembled struct before returning it. An error returned by the
+// function is propagated as the Validation variant of the builder's error.
+//
+// The validation function may return any error that converts into a String.
+// Typestate builders accept the hook too; their build() then returns a Result.
+//
+// A failed validation leaves the builder as it was, so a field can be fixed and
+// build() called again.
+
+use derive_builder::Builder;
+
+#[derive(Builder)]
+#[builder(build_fn(validate = "Range::check"))]
+pub struct Range {
+    min: u32,
+    max: u32,
+    #[builder(default = "1")]
+    step: u32,
+    #[builder(each = "label")]
+    labels: Vec<String>,
+}
+
+impl Range {
+    fn check(&self) -> Result<(), String> {
+        if self.min > self.max {
+            return Err(format!("min {} is greater than max {}", self.min, self.max));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Builder)]
+#[builder(typestate, build_fn(validate = "check_name"))]
+pub struct User {
+    name: String,
+}
+
+fn check_name(user: &User) -> Result<(), &'static str> {
+    if user.name.is_empty() {
+        return Err("name must not be empty");
+    }
+    Ok(())
+}
+
+fn main() {
+    let range = Range::builder().min(1).max(2).build().unwrap();
+    assert_eq!((range.min, range.max), (1, 2));
+
+    let err = Range::builder().min(3).max(2).build().err().unwrap();
+    assert_eq!(err, RangeBuilderError::Validation("min 3 is greater than max 2".to_owned()));
+    assert_eq!(err.to_string(), "validation failed: min 3 is greater than max 2");
+
+    let mut builder = Range::builder();
+    builder.min(5).max(1).label("a".to_owned());
+    assert!(builder.build().is_err());
+    assert!(builder.missing_fields().is_empty());
+    assert!(!builder.is_set_step());
+    let range = builder.max(10).build().unwrap();
+    assert_eq!((range.min, range.max, range.step), (5, 10, 1));
+    assert_eq!(range.labels, ["a"]);
+
+    let user = User::builder().name("ferris".to_owned()).build().unwrap();
+    assert_eq!(user.name, "ferris");
+
+    let err = User::builder().name(String::new()).build().err().unwrap();
+    assert_eq!(err, UserBuilderError::Validation("name must not be empty".to_owned()));
+}
@@ -0,0 +1,82 @@
+// #[builder(each = "...")] is not limited to Vec. Any collection implementing
+// Default and Extend works: sets and queues get a setter taking one element,
+// HashMap and BTreeMap get a setter taking a key and a value.
+//
+// When the item type can not be told from the collection's type, for example
+// for String or a map type other than HashMap and BTreeMap, it is given with
+// #[builder(each(name = "...", item = "..."))]. An item type of (K, V) gets a
+// setter taking a key and a value.
+
+use derive_builder::Builder;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+
+#[derive(Default)]
+pub struct Tags(Vec<String>);
+
+impl Extend<String> for Tags {
+    fn extend<I: IntoIterator<Item = String>>(&mut self, iter: I) {
+        self.0.extend(iter);
+    }
+}
+
+// a map keeping its entries in insertion order
+#[derive(Default)]
+pub struct Entries<K, V>(Vec<(K, V)>);
+
+impl<K, V> Extend<(K, V)> for Entries<K, V> {
+    fn extend<I: IntoIterator<Item = (K, V)>>(&mut self, iter: I) {
+        self.0.extend(iter);
+    }
+}
+
+#[derive(Builder)]
+#[builder(pattern = "owned")]
+pub struct Request {
+    #[builder(each = "header", setter(into))]
+    headers: HashMap<String, String>,
+    #[builder(each = "param")]
+    params: std::collections::BTreeMap<String, u32>,
+    #[builder(each = "flag")]
+    flags: HashSet<char>,
+    #[builder(each = "id")]
+    ids: BTreeSet<u64>,
+    #[builder(each = "step")]
+    steps: VecDeque<u8>,
+    #[builder(each(name = "tag", item = "String"))]
+    tags: Tags,
+    #[builder(each(name = "entry", item = "(String, u32)"))]
+    entries: Entries<String, u32>,
+    #[builder(each(name = "ch", item = "char"))]
+    text: String,
+}
+
+fn main() {
+    let request = Request::builder()
+        .header("Accept", "*/*")
+        .header("Host", "localhost")
+        .param("page".to_owned(), 2)
+        .flag('v')
+        .flag('v')
+        .id(3)
+        .id(1)
+        .step(1)
+        .step(2)
+        .tag("a".to_owned())
+        .entry("b".to_owned(), 2)
+        .entry("a".to_owned(), 1)
+        .ch('h')
+        .ch('i')
+        .build()
+        .unwrap();
+
+    assert_eq!(request.headers.len(), 2);
+    assert_eq!(request.headers["Host"], "localhost");
+    let params: BTreeMap<String, u32> = vec![("page".to_owned(), 2)].into_iter().collect();
+    assert_eq!(request.params, params);
+    assert_eq!(request.flags.len(), 1);
+    assert_eq!(request.ids.into_iter().collect::<Vec<_>>(), vec![1, 3]);
+    assert_eq!(request.steps, VecDeque::from(vec![1, 2]));
+    assert_eq!(request.tags.0, vec!["a"]);
+    assert_eq!(request.entries.0, vec![("b".to_owned(), 2), ("a".to_owned(), 1)]);
+    assert_eq!(request.text, "hi");
+}
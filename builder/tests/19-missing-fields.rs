@@ -0,0 +1,36 @@
+// build() reports every required field that has not been set in a single
+// error instead of stopping at the first one. The same list is available up
+// front from missing_fields(), which does not modify the builder.
+
+use derive_builder::Builder;
+
+#[derive(Builder)]
+pub struct Command {
+    executable: String,
+    args: Vec<String>,
+    #[builder(each = "env")]
+    env: Vec<String>,
+    #[builder(default)]
+    timeout: u64,
+    current_dir: Option<String>,
+}
+
+fn main() {
+    let mut builder = Command::builder();
+    assert_eq!(builder.missing_fields(), ["executable", "args"]);
+
+    let err = builder.build().err().unwrap();
+    assert_eq!(err, CommandBuilderError::MissingFields(vec!["executable", "args"]));
+    assert_eq!(err.to_string(), "fields not set: executable, args");
+
+    builder.args(vec![]);
+    assert_eq!(builder.missing_fields(), ["executable"]);
+
+    // a failed build leaves the fields that were set in place
+    assert!(builder.build().is_err());
+    builder.executable("cargo".to_owned());
+    assert!(builder.missing_fields().is_empty());
+
+    let command = builder.build().unwrap();
+    assert_eq!(command.executable, "cargo");
+}
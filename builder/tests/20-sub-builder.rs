@@ -0,0 +1,101 @@
+// A field whose type also derives Builder can be marked
+// #[builder(sub_builder)]. The parent builder then stores the child's builder
+// and exposes it through a method returning a mutable reference, so nested
+// values can be filled in place. The child is built as part of the parent's
+// build(), and a failure to build it is reported as the SubBuilder variant of
+// the parent's error.
+//
+// Both the parent and the child builder use the default mutable pattern.
+//
+// A failed build leaves the parent and every child builder usable: missing
+// fields of all children are checked before any child is built, and children
+// built before another one fails are put back as they were, leaving fields
+// that fell back to their default unset.
+
+use derive_builder::Builder;
+
+#[derive(Builder)]
+pub struct Server {
+    host: String,
+    #[builder(default = "8080")]
+    port: u16,
+}
+
+#[derive(Builder)]
+pub struct Config {
+    name: String,
+    #[builder(sub_builder)]
+    server: Server,
+}
+
+#[derive(Builder)]
+#[builder(build_fn(validate = "Limits::check"))]
+pub struct Limits {
+    max_connections: u32,
+}
+
+impl Limits {
+    fn check(&self) -> Result<(), &'static str> {
+        if self.max_connections == 0 {
+            return Err("no connections allowed");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Builder)]
+pub struct Deployment {
+    #[builder(sub_builder)]
+    server: Server,
+    #[builder(sub_builder)]
+    limits: Limits,
+}
+
+fn main() {
+    let mut builder = Config::builder();
+    builder.name("app".to_owned());
+    builder.server().host("localhost".to_owned());
+    builder.server().port(80);
+
+    let config = builder.build().unwrap();
+    assert_eq!(config.name, "app");
+    assert_eq!(config.server.host, "localhost");
+    assert_eq!(config.server.port, 80);
+
+    let mut builder = Config::builder();
+    builder.name("app".to_owned());
+    let err = builder.build().err().unwrap();
+    assert_eq!(
+        err,
+        ConfigBuilderError::SubBuilder {
+            field: "server",
+            message: "fields not set: host".to_owned(),
+        },
+    );
+    assert_eq!(err.to_string(), "failed to build server: fields not set: host");
+
+    // the failed build left the parent builder intact
+    builder.server().host("localhost".to_owned());
+    let config = builder.build().unwrap();
+    assert_eq!(config.name, "app");
+    assert_eq!(config.server.port, 8080);
+
+    // a child missing a field leaves the children before it untouched
+    let mut builder = Deployment::builder();
+    builder.server().host("localhost".to_owned());
+    let err = builder.build().err().unwrap();
+    assert_eq!(err.to_string(), "failed to build limits: fields not set: max_connections");
+    assert!(builder.server().missing_fields().is_empty());
+
+    // so does a child failing its validation
+    builder.limits().max_connections(0);
+    let err = builder.build().err().unwrap();
+    assert_eq!(err.to_string(), "failed to build limits: validation failed: no connections allowed");
+    assert_eq!(builder.server().status(), [("host", true, true), ("port", false, false)]);
+    assert!(builder.limits().missing_fields().is_empty());
+
+    builder.limits().max_connections(16);
+    let deployment = builder.build().unwrap();
+    assert_eq!(deployment.server.host, "localhost");
+    assert_eq!(deployment.limits.max_connections, 16);
+}
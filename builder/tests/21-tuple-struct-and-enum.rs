@@ -0,0 +1,51 @@
+// Builders can also be derived for tuple structs and enums.
+//
+// The setters of a tuple struct are named after the position of the field,
+// _0, _1 and so on, unless the field is given a name with
+// #[builder(name = "...")].
+//
+// An enum gets one builder per variant. For a variant Shape::Circle the macro
+// generates Shape::circle_builder() returning a ShapeCircleBuilder whose build()
+// returns a Shape.
+
+use derive_builder::Builder;
+
+#[derive(Builder)]
+pub struct Point(i32, #[builder(name = "y")] i32, #[builder(default)] i32);
+
+#[derive(Builder)]
+pub struct Marker;
+
+#[derive(Debug, PartialEq, Builder)]
+pub enum Shape<T> {
+    Circle {
+        radius: T,
+        #[builder(default)]
+        filled: bool,
+    },
+    Rectangle(T, T),
+    RoundedRect(T, T, Option<T>),
+    Empty,
+}
+
+fn main() {
+    let point = Point::builder()._0(1).y(2).build().unwrap();
+    assert_eq!((point.0, point.1, point.2), (1, 2, 0));
+
+    let err = Point::builder()._0(1).build().err().unwrap();
+    assert_eq!(err, PointBuilderError::MissingFields(vec!["y"]));
+
+    let _marker: Marker = Marker::builder().build().unwrap();
+
+    let circle = Shape::circle_builder().radius(1.5).build().unwrap();
+    assert_eq!(circle, Shape::Circle { radius: 1.5, filled: false });
+
+    let rectangle = Shape::rectangle_builder()._0(1).build();
+    assert_eq!(rectangle, Err(ShapeRectangleBuilderError::MissingFields(vec!["_1"])));
+
+    let rounded = Shape::rounded_rect_builder()._0(2)._1(3)._2(1).build().unwrap();
+    assert_eq!(rounded, Shape::RoundedRect(2, 3, Some(1)));
+
+    let empty: Shape<u8> = Shape::empty_builder().build().unwrap();
+    assert_eq!(empty, Shape::Empty);
+}
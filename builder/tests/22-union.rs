@@ -0,0 +1,12 @@
+// Unions are not supported and are rejected with an error pointing at the
+// union instead of a panic.
+
+use derive_builder::Builder;
+
+#[derive(Builder)]
+pub union Value {
+    int: u32,
+    float: f32,
+}
+
+fn main() {}
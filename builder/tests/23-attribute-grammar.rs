@@ -0,0 +1,45 @@
+// Fields and structs may carry other attributes, such as doc comments, next to
+// their builder attributes, and the keys of a builder attribute may be spread
+// over several #[builder(...)] attributes.
+
+use derive_builder::Builder;
+
+/// A command to run.
+#[derive(Debug, Builder)]
+#[builder(pattern = "owned")]
+#[allow(dead_code)]
+#[builder(build_fn(validate = "Command::check"))]
+pub struct Command {
+    /// The program to run.
+    #[builder(setter(into))]
+    executable: String,
+    /// Its arguments, one at a time.
+    #[builder(each = "arg")]
+    #[builder(setter(into))]
+    args: Vec<String>,
+    #[cfg_attr(not(test), doc = "Where to run it.")]
+    #[builder(default = "String::from(\"/\")", setter(into))]
+    current_dir: String,
+}
+
+impl Command {
+    fn check(&self) -> Result<(), String> {
+        if self.executable.is_empty() {
+            return Err("executable is empty".to_owned());
+        }
+        Ok(())
+    }
+}
+
+fn main() {
+    let command = Command::builder()
+        .executable("cargo")
+        .arg("build")
+        .arg("--release")
+        .build()
+        .unwrap();
+
+    assert_eq!(command.executable, "cargo");
+    assert_eq!(command.args, vec!["build", "--release"]);
+    assert_eq!(command.current_dir, "/");
+}
@@ -0,0 +1,22 @@
+// Unknown keys, keys given twice and values of the wrong type are reported
+// with an error pointing at the offending key or value. The errors of every
+// field are reported at once.
+
+use derive_builder::Builder;
+
+#[derive(Builder)]
+pub struct Command {
+    #[builder(setter(into, strip_option = "no"))]
+    executable: Option<String>,
+    #[builder(each = "arg")]
+    #[builder(each = "argument")]
+    args: Vec<String>,
+    #[builder(defualt)]
+    env: Vec<String>,
+    #[builder(each = arg)]
+    current_dir: Vec<String>,
+    #[builder(each = "ch")]
+    input: String,
+}
+
+fn main() {}
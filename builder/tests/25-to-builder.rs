@@ -0,0 +1,61 @@
+// A struct can be turned back into a builder with every field set, either by
+// value through From<Command> for CommandBuilder or from a reference with
+// Command::to_builder(), so a known-good value can be tweaked and rebuilt.
+//
+// to_builder() clones every field and is only available when all field types
+// are Clone. From is always available.
+
+use derive_builder::Builder;
+
+#[derive(Debug, PartialEq, Builder)]
+pub struct Command {
+    executable: String,
+    #[builder(each = "arg")]
+    args: Vec<String>,
+    #[builder(default)]
+    env: Vec<String>,
+    current_dir: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Builder)]
+#[builder(typestate)]
+pub struct Point<T> {
+    x: T,
+    y: T,
+}
+
+pub struct Handle;
+
+#[derive(Builder)]
+pub struct Resource {
+    name: String,
+    handle: Handle,
+}
+
+fn main() {
+    let command = Command::builder()
+        .executable("cargo".to_owned())
+        .arg("build".to_owned())
+        .current_dir("..".to_owned())
+        .build()
+        .unwrap();
+
+    let release = command.to_builder().arg("--release".to_owned()).build().unwrap();
+    assert_eq!(release.executable, "cargo");
+    assert_eq!(release.args, vec!["build", "--release"]);
+    assert_eq!(release.current_dir, Some("..".to_owned()));
+
+    let mut builder = CommandBuilder::from(command);
+    builder.executable("rustc".to_owned());
+    let rustc = builder.build().unwrap();
+    assert_eq!(rustc.executable, "rustc");
+    assert_eq!(rustc.args, vec!["build"]);
+
+    let point = Point::builder().x(1).y(2).build();
+    assert_eq!(point.to_builder().build(), Point { x: 1, y: 2 });
+
+    let resource = Resource::builder().name("db".to_owned()).handle(Handle).build().unwrap();
+    let mut builder = ResourceBuilder::from(resource);
+    builder.name("cache".to_owned());
+    assert_eq!(builder.build().unwrap().name, "cache");
+}
@@ -0,0 +1,57 @@
+// Partial builders can be layered with merge(). Every field set in the other
+// builder overrides this builder, while the items of each = "..." collections
+// are appended, so configuration from defaults, a file and the command line
+// can be composed in priority order. Collections that can only be extended
+// are replaced instead. Sub builders are merged recursively.
+
+use derive_builder::Builder;
+
+// a collection that can be extended but not iterated
+#[derive(Default)]
+pub struct Tags(Vec<String>);
+
+impl Extend<String> for Tags {
+    fn extend<I: IntoIterator<Item = String>>(&mut self, iter: I) {
+        self.0.extend(iter);
+    }
+}
+
+#[derive(Builder)]
+pub struct Server {
+    host: String,
+    #[builder(default = "8080")]
+    port: u16,
+    #[builder(each(name = "tag", item = "String"))]
+    tags: Tags,
+}
+
+#[derive(Builder)]
+pub struct Config {
+    name: String,
+    #[builder(each = "feature")]
+    features: Vec<String>,
+    log_file: Option<String>,
+    #[builder(sub_builder)]
+    server: Server,
+}
+
+fn main() {
+    let mut defaults = Config::builder();
+    defaults.name("app".to_owned()).feature("logging".to_owned());
+    defaults.server().host("localhost".to_owned()).port(80).tag("default".to_owned());
+
+    let mut file = Config::builder();
+    file.feature("metrics".to_owned()).log_file("app.log".to_owned());
+    file.server().port(8000).tag("file".to_owned());
+
+    let mut args = Config::builder();
+    args.name("cli".to_owned());
+
+    let config = defaults.merge(file).merge(args).build().unwrap();
+    assert_eq!(config.name, "cli");
+    assert_eq!(config.features, vec!["logging", "metrics"]);
+    assert_eq!(config.log_file, Some("app.log".to_owned()));
+    assert_eq!(config.server.host, "localhost");
+    assert_eq!(config.server.port, 8000);
+    assert_eq!(config.server.tags.0, vec!["file"]);
+}
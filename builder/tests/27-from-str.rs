@@ -0,0 +1,74 @@
+// With #[builder(from_str)] the builder can be filled from string key/value
+// pairs. set_from_str(key, value) parses the value with FromStr into the field
+// named by key, and from_pairs(iter) builds a new builder from many pairs.
+//
+// Each fields append one parsed item per call, maps take "key=value" items,
+// and the fields of a sub builder, which must also use from_str, are reached
+// with dotted keys. An unknown key or a value that fails to parse is reported
+// through the UnknownField and Parse variants of the builder's error. Fields
+// marked #[builder(from_str = false)] can only be set through their setter,
+// which is how fields whose type does not implement FromStr are left out.
+
+use derive_builder::Builder;
+use std::collections::HashMap;
+
+#[derive(Builder)]
+#[builder(from_str)]
+pub struct Server {
+    host: String,
+    #[builder(default = "8080")]
+    port: u16,
+}
+
+pub struct Handle;
+
+#[derive(Builder)]
+#[builder(from_str)]
+pub struct Config {
+    name: String,
+    #[builder(each = "feature")]
+    features: Vec<String>,
+    #[builder(each = "var")]
+    env: HashMap<String, String>,
+    timeout: Option<u64>,
+    #[builder(sub_builder)]
+    server: Server,
+    #[builder(from_str = false, default = "Handle")]
+    handle: Handle,
+}
+
+fn main() {
+    let pairs = vec![
+        ("name", "app"),
+        ("features", "logging"),
+        ("features", "metrics"),
+        ("env", "RUST_LOG=debug"),
+        ("timeout", "30"),
+        ("server.host", "localhost"),
+        ("server.port", "80"),
+    ];
+    let config = ConfigBuilder::from_pairs(pairs).unwrap().build().unwrap();
+    assert_eq!(config.name, "app");
+    assert_eq!(config.features, vec!["logging", "metrics"]);
+    assert_eq!(config.env["RUST_LOG"], "debug");
+    assert_eq!(config.timeout, Some(30));
+    assert_eq!(config.server.host, "localhost");
+    assert_eq!(config.server.port, 80);
+    let _ = config.handle;
+
+    let mut builder = Config::builder();
+    builder.set_from_str("name", "app").unwrap().set_from_str("timeout", "60").unwrap();
+
+    let err = builder.set_from_str("handle", "x").err().unwrap();
+    assert_eq!(err, ConfigBuilderError::UnknownField("handle".to_owned()));
+    assert_eq!(err.to_string(), "unknown field `handle`");
+
+    let err = builder.set_from_str("server.hots", "localhost").err().unwrap();
+    assert_eq!(err, ConfigBuilderError::UnknownField("server.hots".to_owned()));
+
+    let err = builder.set_from_str("server.port", "eighty").err().unwrap();
+    assert_eq!(err.to_string(), "failed to parse server.port: invalid digit found in string");
+
+    let err = builder.set_from_str("env", "RUST_LOG").err().unwrap();
+    assert_eq!(err.to_string(), "failed to parse env: expected `key=value`");
+}
@@ -0,0 +1,50 @@
+// A field marked #[builder(env = "VAR")] falls back to the environment
+// variable VAR, parsed with FromStr, when its setter was not called. The
+// variable is consulted before any default, and a required field is only
+// missing if the variable is not set either. A variable that can not be
+// parsed is reported through the Env variant of the builder's error, naming
+// both the field and the variable.
+
+use derive_builder::Builder;
+use std::env;
+
+#[derive(Builder)]
+pub struct Service {
+    #[builder(env = "SERVICE_TEST_HOST")]
+    host: String,
+    #[builder(env = "SERVICE_TEST_PORT", default = "8080")]
+    port: u16,
+    #[builder(env = "SERVICE_TEST_WORKERS")]
+    workers: Option<usize>,
+}
+
+fn main() {
+    let err = Service::builder().build().err().unwrap();
+    assert_eq!(err, ServiceBuilderError::MissingFields(vec!["host"]));
+
+    env::set_var("SERVICE_TEST_HOST", "example.com");
+    let service = Service::builder().build().unwrap();
+    assert_eq!(service.host, "example.com");
+    assert_eq!(service.port, 8080);
+    assert_eq!(service.workers, None);
+
+    env::set_var("SERVICE_TEST_PORT", "80");
+    env::set_var("SERVICE_TEST_WORKERS", "4");
+    let service = Service::builder().host("localhost".to_owned()).build().unwrap();
+    assert_eq!(service.host, "localhost");
+    assert_eq!(service.port, 80);
+    assert_eq!(service.workers, Some(4));
+
+    env::set_var("SERVICE_TEST_PORT", "eighty");
+    let err = Service::builder().build().err().unwrap();
+    assert_eq!(
+        err,
+        ServiceBuilderError::Env {
+            field: "port",
+            var: "SERVICE_TEST_PORT",
+            message: "invalid digit found in string".to_owned(),
+        }
+    );
+    assert_eq!(err.to_string(), "failed to read port from $SERVICE_TEST_PORT: invalid digit found in string");
+    assert_eq!(Service::builder().port(443).build().unwrap().port, 443);
+}
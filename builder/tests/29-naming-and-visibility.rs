@@ -0,0 +1,62 @@
+// Controls over what the builder looks like:
+//
+//   - #[builder(skip)] leaves a field out of the builder, build() sets it to
+//     its Default,
+//   - #[builder(setter(name = "..."))] renames a field's setter,
+//   - #[builder(setter(prefix = "..."))] prefixes a field's setter,
+//   - #[builder(name = "...")] on the struct renames the builder, its error
+//     type is named after it,
+//   - #[builder(vis = "...")] on the struct sets the visibility of the
+//     builder, its methods and its error type, which otherwise follow the
+//     visibility of the struct.
+
+mod config {
+    use derive_builder::Builder;
+
+    #[derive(Debug, Builder)]
+    #[builder(name = "CommandConfig", vis = "pub(crate)")]
+    pub struct Command {
+        #[builder(setter(name = "program"))]
+        pub executable: String,
+        #[builder(each = "arg", setter(prefix = "with_"))]
+        pub args: Vec<String>,
+        #[builder(setter(prefix = "with_"))]
+        pub current_dir: Option<String>,
+        #[builder(skip)]
+        pub runs: u32,
+    }
+
+    #[derive(Builder)]
+    pub(super) struct Job {
+        pub name: String,
+    }
+}
+
+use config::{Command, CommandConfig, CommandConfigError, Job};
+
+fn main() {
+    let mut builder: CommandConfig = Command::builder();
+    let command = builder
+        .program("cargo".to_owned())
+        .arg("build".to_owned())
+        .with_current_dir("..".to_owned())
+        .build()
+        .unwrap();
+    assert_eq!(command.executable, "cargo");
+    assert_eq!(command.args, vec!["build"]);
+    assert_eq!(command.current_dir, Some("..".to_owned()));
+    assert_eq!(command.runs, 0);
+
+    let command = Command::builder()
+        .program("rustc".to_owned())
+        .with_args(vec!["--version".to_owned()])
+        .build()
+        .unwrap();
+    assert_eq!(command.args, vec!["--version"]);
+
+    let err: CommandConfigError = Command::builder().build().err().unwrap();
+    assert_eq!(err, CommandConfigError::MissingFields(vec!["executable"]));
+
+    let job = Job::builder().name("nightly".to_owned()).build().unwrap();
+    assert_eq!(job.name, "nightly");
+}
@@ -0,0 +1,42 @@
+// A field marked #[builder(try_setter)] gets a try_ setter next to its normal
+// setter. It takes any value convertible with TryInto and returns the
+// conversion error instead of setting the field when the value does not fit,
+// so wider or untrusted inputs are checked where the field is set.
+
+use derive_builder::Builder;
+use std::num::TryFromIntError;
+
+#[derive(Builder)]
+pub struct Server {
+    host: String,
+    #[builder(try_setter)]
+    port: u16,
+    #[builder(try_setter)]
+    workers: Option<u8>,
+}
+
+#[derive(Builder)]
+#[builder(typestate)]
+pub struct Limits {
+    #[builder(try_setter)]
+    connections: u16,
+}
+
+fn main() -> Result<(), TryFromIntError> {
+    let mut builder = Server::builder();
+    builder.host("localhost".to_owned()).try_port(8080u64)?.try_workers(4i32)?;
+    let server = builder.build().unwrap();
+    assert_eq!(server.port, 8080);
+    assert_eq!(server.workers, Some(4));
+
+    let mut builder = Server::builder();
+    assert!(builder.try_port(70000u32).is_err());
+    assert!(builder.try_port(-1i64).is_err());
+    assert_eq!(builder.missing_fields(), vec!["host", "port"]);
+
+    let limits = Limits::builder().try_connections(100usize)?.build();
+    assert_eq!(limits.connections, 100);
+    assert!(Limits::builder().try_connections(1usize << 20).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,54 @@
+// Every each = "arg" setter comes with extend_arg(iter), adding all items of
+// an iterator, and clear_<field>(), emptying the collection again.
+//
+// build() moves the collections out of the builder, so the items do not need
+// to implement Clone.
+
+use derive_builder::Builder;
+use std::collections::BTreeMap;
+
+#[derive(Debug, PartialEq)]
+pub struct Task(&'static str);
+
+#[derive(Builder)]
+pub struct Pipeline {
+    #[builder(each = "task")]
+    tasks: Vec<Task>,
+    #[builder(each = "label")]
+    labels: BTreeMap<String, String>,
+}
+
+#[derive(Builder)]
+#[builder(typestate)]
+pub struct Batch {
+    name: String,
+    #[builder(each = "task")]
+    tasks: Vec<Task>,
+}
+
+fn main() {
+    let mut builder = Pipeline::builder();
+    builder
+        .task(Task("fetch"))
+        .extend_task(vec![Task("build"), Task("test")])
+        .extend_label(vec![("team".to_owned(), "infra".to_owned())])
+        .label("tier".to_owned(), "1".to_owned());
+    let pipeline = builder.build().unwrap();
+    assert_eq!(pipeline.tasks, vec![Task("fetch"), Task("build"), Task("test")]);
+    assert_eq!(pipeline.labels.len(), 2);
+
+    let mut builder = Pipeline::builder();
+    builder.task(Task("fetch")).clear_tasks().task(Task("deploy"));
+    builder.label("tier".to_owned(), "1".to_owned()).clear_labels();
+    let pipeline = builder.build().unwrap();
+    assert_eq!(pipeline.tasks, vec![Task("deploy")]);
+    assert!(pipeline.labels.is_empty());
+
+    let batch = Batch::builder()
+        .extend_task(vec![Task("a"), Task("b")])
+        .clear_tasks()
+        .task(Task("c"))
+        .name("nightly".to_owned())
+        .build();
+    assert_eq!(batch.tasks, vec![Task("c")]);
+}
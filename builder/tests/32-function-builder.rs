@@ -0,0 +1,64 @@
+// #[builder] on a function generates a builder for its arguments, giving
+// functions with many parameters named and defaulted arguments. For a
+// function connect the builder is ConnectArgs, and its call() method builds
+// the arguments and calls the function with them, returning the function's
+// result or the builder's error if an argument is missing.
+//
+// The parameters take the same builder attributes as the fields of a struct
+// deriving Builder, and the arguments of the macro are those of the struct,
+// e.g. #[builder(pattern = "owned")].
+//
+// Generic parameters may appear in the return type only, and the call() of an
+// unsafe fn is unsafe as well.
+
+use derive_builder::builder;
+
+#[builder]
+fn connect(host: &str, #[builder(default = "80")] port: u16, #[builder(each = "option")] options: Vec<String>) -> String {
+    format!("{}:{} {:?}", host, port, options)
+}
+
+#[builder(pattern = "owned", name = "SumCall")]
+pub fn sum<T: std::iter::Sum<T>>(#[builder(each = "value")] values: Vec<T>) -> T {
+    values.into_iter().sum()
+}
+
+#[builder]
+fn parse_it<T: std::str::FromStr>(s: &str) -> Option<T> {
+    s.parse().ok()
+}
+
+#[builder]
+fn ping() -> &'static str {
+    "pong"
+}
+
+/// # Safety
+///
+/// `ptr` must point to a valid `u32`.
+#[builder]
+unsafe fn read(ptr: *const u32) -> u32 {
+    *ptr
+}
+
+fn main() {
+    let mut args = ConnectArgs::default();
+    args.host("localhost").option("tls".to_owned());
+    assert_eq!(args.call().unwrap(), "localhost:80 [\"tls\"]");
+
+    let err = ConnectArgs::default().port(8080).call().err().unwrap();
+    assert_eq!(err, ConnectArgsError::MissingFields(vec!["host"]));
+
+    let total = SumCall::default().value(1).value(2).value(3).call().unwrap();
+    assert_eq!(total, 6);
+
+    let mut args = ParseItArgs::<u8>::default();
+    assert_eq!(args.s("42").call().unwrap(), Some(42));
+
+    assert_eq!(PingArgs::default().call().unwrap(), "pong");
+
+    let value = 7;
+    let mut args = ReadArgs::default();
+    args.ptr(&value);
+    assert_eq!(unsafe { args.call() }.unwrap(), 7);
+}
@@ -0,0 +1,43 @@
+// With #[builder(no_std)] the generated code only refers to core and alloc,
+// so the builder also works in crates without std that link alloc. The error
+// type then does not implement std::error::Error, and env = "..." fallbacks
+// are not available.
+//
+// This test is a no_std crate that links std under another name, so any path
+// through ::std in the generated code fails to resolve.
+
+#![no_std]
+
+extern crate alloc;
+extern crate std as rust_std;
+
+use alloc::borrow::ToOwned;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use derive_builder::Builder;
+
+#[derive(Debug, PartialEq, Builder)]
+#[builder(no_std, from_str)]
+pub struct Sensor {
+    name: String,
+    #[builder(default = "100")]
+    interval_ms: u32,
+    #[builder(each = "channel")]
+    channels: Vec<u8>,
+    calibration: Option<i16>,
+}
+
+fn main() {
+    let mut builder = Sensor::builder();
+    builder.name("thermo".to_owned()).channel(1).channel(2);
+    builder.set_from_str("calibration", "-3").unwrap();
+    let sensor = builder.build().unwrap();
+    assert_eq!(sensor.interval_ms, 100);
+    assert_eq!(sensor.channels, vec![1, 2]);
+    assert_eq!(sensor.calibration, Some(-3));
+
+    let err = Sensor::builder().build().err().unwrap();
+    assert_eq!(err.to_string(), "fields not set: name");
+    let _ = rust_std::process::id();
+}
@@ -0,0 +1,48 @@
+// A default can be computed from other fields with
+// #[builder(default_with = "path", depends_on(a, b))]. When the field is not
+// set, build() calls path(&a, &b) with the resolved values of a and b, which
+// may themselves be defaulted, resolving fields in dependency order. A field
+// depending on itself, directly or through others, is a compile error.
+
+use derive_builder::Builder;
+
+fn read_timeout(connect_timeout: &u64) -> u64 {
+    2 * connect_timeout
+}
+
+fn total_timeout(connect_timeout: &u64, read_timeout: &u64, retries: &Option<u32>) -> u64 {
+    (connect_timeout + read_timeout) * u64::from(retries.unwrap_or(0) + 1)
+}
+
+fn default_name() -> String {
+    "client".to_owned()
+}
+
+#[derive(Builder)]
+pub struct Client {
+    #[builder(default_with = "total_timeout", depends_on(connect_timeout, read_timeout, retries))]
+    total_timeout: u64,
+    #[builder(default_with = "read_timeout", depends_on(connect_timeout))]
+    read_timeout: u64,
+    #[builder(default = "10")]
+    connect_timeout: u64,
+    retries: Option<u32>,
+    #[builder(default_with = "default_name")]
+    name: String,
+}
+
+fn main() {
+    let client = Client::builder().build().unwrap();
+    assert_eq!(client.connect_timeout, 10);
+    assert_eq!(client.read_timeout, 20);
+    assert_eq!(client.total_timeout, 30);
+    assert_eq!(client.name, "client");
+
+    let client = Client::builder().connect_timeout(5).retries(1).build().unwrap();
+    assert_eq!(client.read_timeout, 10);
+    assert_eq!(client.total_timeout, 30);
+
+    let client = Client::builder().read_timeout(1).total_timeout(100).build().unwrap();
+    assert_eq!(client.read_timeout, 1);
+    assert_eq!(client.total_timeout, 100);
+}
@@ -0,0 +1,26 @@
+// Dependencies between computed defaults must not form a cycle, and must name
+// fields of the builder.
+
+use derive_builder::Builder;
+
+fn sum(a: &u32) -> u32 {
+    *a
+}
+
+#[derive(Builder)]
+pub struct Cycle {
+    #[builder(default_with = "sum", depends_on(b))]
+    a: u32,
+    #[builder(default_with = "sum", depends_on(c))]
+    b: u32,
+    #[builder(default_with = "sum", depends_on(a))]
+    c: u32,
+}
+
+#[derive(Builder)]
+pub struct Unknown {
+    #[builder(default_with = "sum", depends_on(d))]
+    a: u32,
+}
+
+fn main() {}
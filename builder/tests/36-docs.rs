@@ -0,0 +1,128 @@
+//! The doc comments of a field are carried over to its builder field and its
+//! setters, and the builder itself is documented with a list of its required
+//! and optional fields, including their defaults. Every other generated item,
+//! the builder's methods and its error type, is documented as well, so a crate
+//! with #![deny(missing_docs)] can derive builders. Setters of undocumented
+//! fields get a short generated description instead.
+
+#![deny(missing_docs)]
+
+use derive_builder::{builder, Builder};
+
+/// Where a command runs.
+#[derive(Builder)]
+#[builder(from_str)]
+pub struct Host {
+    /// The host name.
+    name: String,
+    /// The SSH port.
+    #[builder(default = "22")]
+    port: u16,
+}
+
+/// A command to run.
+#[derive(Builder)]
+#[builder(from_str, build_fn(validate = "Command::check"))]
+pub struct Command {
+    /// The program to run.
+    #[builder(validate = "non_empty")]
+    executable: String,
+    /// Its arguments, added one at a time.
+    #[builder(each = "arg")]
+    args: Vec<String>,
+    /// Where to run it.
+    #[builder(default = "String::from(\"/\")")]
+    current_dir: String,
+    /// How many times to retry.
+    #[builder(try_setter, env = "COMMAND_RETRIES", default)]
+    retries: u8,
+    /// The host to run it on.
+    #[builder(sub_builder)]
+    host: Host,
+    #[builder(default)]
+    verbose: bool,
+}
+
+impl Command {
+    fn check(&self) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
+
+fn non_empty(s: &String) -> Result<(), &'static str> {
+    if s.is_empty() {
+        Err("must not be empty")
+    } else {
+        Ok(())
+    }
+}
+
+/// A point in the plane.
+#[derive(Builder)]
+#[builder(typestate, build_fn(validate = "Point::check"))]
+pub struct Point {
+    /// Horizontal position.
+    #[builder(try_setter)]
+    x: i32,
+    /// Vertical position.
+    #[builder(default)]
+    y: i32,
+    /// Tags, added one at a time.
+    #[builder(each = "tag")]
+    tags: Vec<String>,
+    #[builder(each = "label")]
+    labels: Vec<String>,
+}
+
+impl Point {
+    fn check(&self) -> Result<(), &'static str> {
+        Ok(())
+    }
+}
+
+/// A pair of values.
+#[derive(Builder)]
+pub struct Pair(
+    /// The first value.
+    u32,
+    /// The second value.
+    #[builder(name = "second")]
+    u32,
+);
+
+/// A shape.
+#[derive(Builder)]
+pub enum Shape {
+    /// A circle.
+    Circle {
+        /// Its radius.
+        radius: u32,
+    },
+}
+
+/// Connects to a host.
+#[builder]
+pub fn connect(host: &str, #[builder(default = "22")] port: u16) -> String {
+    format!("{}:{}", host, port)
+}
+
+fn main() {
+    let mut command = Command::builder();
+    command.executable("cargo".to_owned()).arg("build".to_owned());
+    command.host().name("localhost".to_owned());
+    let command = command.build().unwrap();
+    assert_eq!(command.current_dir, "/");
+    assert_eq!(command.args, vec!["build"]);
+
+    let point = Point::builder().x(1).tag("origin".to_owned()).build().unwrap();
+    assert_eq!((point.x, point.y), (1, 0));
+    assert_eq!(point.tags, vec!["origin"]);
+
+    let pair = Pair::builder()._0(1).second(2).build().unwrap();
+    assert_eq!((pair.0, pair.1), (1, 2));
+
+    let circle = Shape::circle_builder().radius(1).build().unwrap();
+    assert!(matches!(circle, Shape::Circle { radius: 1 }));
+
+    assert_eq!(ConnectArgs::default().host("example.com").call().unwrap(), "example.com:22");
+}
@@ -0,0 +1,88 @@
+// Builders can be asked which fields are set. is_set_<field>() tells whether
+// a field was given a value, reset_<field>() and reset_all() forget values
+// again, and status() reports every field as a (name, required, is_set)
+// entry. An each = "..." field counts as set once one of its setters is
+// called, even for collections that can only be extended, and a sub builder
+// once any of its fields is set.
+
+use derive_builder::Builder;
+
+#[derive(Builder)]
+pub struct Server {
+    host: String,
+    #[builder(default = "8080")]
+    port: u16,
+}
+
+#[derive(Builder)]
+pub struct Config {
+    name: String,
+    #[builder(each = "feature")]
+    features: Vec<String>,
+    log_file: Option<String>,
+    #[builder(sub_builder)]
+    server: Server,
+}
+
+// a collection that can be extended but not iterated
+#[derive(Default)]
+pub struct Tags(Vec<String>);
+
+impl Extend<String> for Tags {
+    fn extend<I: IntoIterator<Item = String>>(&mut self, iter: I) {
+        self.0.extend(iter);
+    }
+}
+
+#[derive(Builder)]
+pub struct Post {
+    #[builder(each(name = "tag", item = "String"))]
+    tags: Tags,
+}
+
+#[derive(Builder)]
+#[builder(pattern = "owned")]
+pub struct Job {
+    name: String,
+    #[builder(default)]
+    priority: u8,
+}
+
+fn main() {
+    let mut builder = Config::builder();
+    assert_eq!(
+        builder.status(),
+        [
+            ("name", true, false),
+            ("features", false, false),
+            ("log_file", false, false),
+            ("server", false, false),
+        ]
+    );
+
+    builder.name("app".to_owned()).feature("logging".to_owned());
+    builder.server().port(80);
+    assert!(builder.is_set_name());
+    assert!(builder.is_set_features());
+    assert!(!builder.is_set_log_file());
+    assert!(builder.is_set_server());
+
+    builder.reset_features().reset_name();
+    assert!(!builder.is_set_name());
+    assert!(!builder.is_set_features());
+    assert_eq!(builder.missing_fields(), vec!["name"]);
+
+    builder.log_file("app.log".to_owned()).reset_all();
+    assert!(builder.status().iter().all(|(_, _, is_set)| !is_set));
+
+    let mut post = Post::builder();
+    assert_eq!(post.status(), [("tags", false, false)]);
+    post.tag("rust".to_owned());
+    assert!(post.is_set_tags());
+    post.clear_tags();
+    assert!(!post.is_set_tags());
+
+    let job = Job::builder().name("nightly".to_owned()).priority(3).reset_priority();
+    assert_eq!(job.status(), [("name", true, true), ("priority", false, false)]);
+    assert!(!job.reset_all().is_set_name());
+}
@@ -0,0 +1,83 @@
+// A field can be checked on its own with #[builder(validate = "path")]. Once
+// build() has resolved the field, including defaults, it calls path(&value),
+// which returns Result<(), E> for some E: Into<String>. An error makes build()
+// fail with InvalidField, naming the field and carrying the message. The
+// builder stays as it was, so the field can be fixed and build() called again.
+
+use derive_builder::Builder;
+
+fn non_empty(name: &String) -> Result<(), &'static str> {
+    if name.is_empty() {
+        Err("must not be empty")
+    } else {
+        Ok(())
+    }
+}
+
+fn non_zero(port: &u16) -> Result<(), String> {
+    if *port == 0 {
+        Err(format!("{} is not a valid port", port))
+    } else {
+        Ok(())
+    }
+}
+
+fn at_most_three(tags: &Vec<String>) -> Result<(), &'static str> {
+    if tags.len() > 3 {
+        Err("at most 3 tags")
+    } else {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Builder)]
+pub struct Server {
+    #[builder(validate = "non_empty")]
+    name: String,
+    #[builder(default = "0", validate = "non_zero")]
+    port: u16,
+    #[builder(each = "tag", validate = "at_most_three")]
+    tags: Vec<String>,
+}
+
+fn main() {
+    let server = Server::builder()
+        .name("db".to_owned())
+        .port(5432)
+        .tag("primary".to_owned())
+        .build()
+        .unwrap();
+    assert_eq!(server.port, 5432);
+
+    let err = Server::builder().name(String::new()).port(80).build().unwrap_err();
+    assert_eq!(
+        err,
+        ServerBuilderError::InvalidField {
+            field: "name",
+            message: "must not be empty".to_owned(),
+        }
+    );
+    assert_eq!(err.to_string(), "invalid name: must not be empty");
+
+    // defaults are validated too
+    let err = Server::builder().name("db".to_owned()).build().unwrap_err();
+    assert_eq!(err.to_string(), "invalid port: 0 is not a valid port");
+
+    let err = Server::builder()
+        .name("db".to_owned())
+        .port(80)
+        .extend_tag(vec!["a".to_owned(), "b".to_owned(), "c".to_owned(), "d".to_owned()])
+        .build()
+        .unwrap_err();
+    assert_eq!(err.to_string(), "invalid tags: at most 3 tags");
+
+    let mut builder = Server::builder();
+    builder.name("db".to_owned()).port(0).tag("primary".to_owned());
+    let err = builder.build().unwrap_err();
+    assert_eq!(err.to_string(), "invalid port: 0 is not a valid port");
+    assert!(builder.missing_fields().is_empty());
+    assert!(builder.is_set_name() && builder.is_set_port() && builder.is_set_tags());
+    let server = builder.port(5432).build().unwrap();
+    assert_eq!((server.name.as_str(), server.port), ("db", 5432));
+    assert_eq!(server.tags, ["primary"]);
+}
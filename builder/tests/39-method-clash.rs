@@ -0,0 +1,22 @@
+// Every builder has build, missing_fields, merge, reset_all and status
+// methods, and the builder of a function also has call. A field whose setter or other generated methods would take one of
+// those names is rejected with an error suggesting how to rename them, instead
+// of a duplicate definition error pointing at the derive.
+
+use derive_builder::{builder, Builder};
+
+#[derive(Builder)]
+pub struct Job {
+    name: String,
+    status: String,
+    all: bool,
+    #[builder(setter(name = "state"))]
+    merge: u8,
+}
+
+#[builder]
+fn schedule(call: u8) -> u8 {
+    call
+}
+
+fn main() {}
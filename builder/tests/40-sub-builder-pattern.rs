@@ -0,0 +1,32 @@
+// A sub builder is edited in place through a mutable reference and put back
+// when the parent's build fails, so both the parent and the child builder must
+// use the default mutable pattern. A parent with another pattern is rejected
+// at the sub_builder field, and so is a child builder with another pattern.
+
+use derive_builder::Builder;
+
+#[derive(Builder)]
+pub struct Server {
+    host: String,
+}
+
+#[derive(Builder)]
+#[builder(pattern = "owned")]
+pub struct Config {
+    #[builder(sub_builder)]
+    server: Server,
+}
+
+#[derive(Builder)]
+#[builder(pattern = "immutable")]
+pub struct Limits {
+    max_connections: u32,
+}
+
+#[derive(Builder)]
+pub struct Deployment {
+    #[builder(sub_builder)]
+    limits: Limits,
+}
+
+fn main() {}
@@ -0,0 +1,16 @@
+// A builder with #[builder(from_str)] parses every field from a string, so a
+// field whose type does not implement FromStr is an error at that field,
+// suggesting to leave it out with #[builder(from_str = false)].
+
+use derive_builder::Builder;
+
+pub struct Handle;
+
+#[derive(Builder)]
+#[builder(from_str)]
+pub struct Task {
+    name: String,
+    handle: Handle,
+}
+
+fn main() {}
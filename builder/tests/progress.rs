@@ -10,4 +10,36 @@ fn tests() {
     t.pass("tests/07-repeated-field.rs");
     t.compile_fail("tests/08-unrecognized-attribute.rs");
     t.pass("tests/09-redefined-prelude-types.rs");
+    t.pass("tests/10-default-field.rs");
+    t.pass("tests/11-builder-error.rs");
+    t.pass("tests/12-typestate.rs");
+    t.compile_fail("tests/13-typestate-missing-field.rs");
+    t.pass("tests/14-generics.rs");
+    t.pass("tests/15-setter-options.rs");
+    t.pass("tests/16-builder-pattern.rs");
+    t.pass("tests/17-validate.rs");
+    t.pass("tests/18-each-collections.rs");
+    t.pass("tests/19-missing-fields.rs");
+    t.pass("tests/20-sub-builder.rs");
+    t.pass("tests/21-tuple-struct-and-enum.rs");
+    t.compile_fail("tests/22-union.rs");
+    t.pass("tests/23-attribute-grammar.rs");
+    t.compile_fail("tests/24-attribute-errors.rs");
+    t.pass("tests/25-to-builder.rs");
+    t.pass("tests/26-merge.rs");
+    t.pass("tests/27-from-str.rs");
+    t.pass("tests/28-env.rs");
+    t.pass("tests/29-naming-and-visibility.rs");
+    t.pass("tests/30-try-setter.rs");
+    t.pass("tests/31-each-companions.rs");
+    t.pass("tests/32-function-builder.rs");
+    t.pass("tests/33-no-std.rs");
+    t.pass("tests/34-default-with.rs");
+    t.compile_fail("tests/35-default-with-cycle.rs");
+    t.pass("tests/36-docs.rs");
+    t.pass("tests/37-introspection.rs");
+    t.pass("tests/38-field-validate.rs");
+    t.compile_fail("tests/39-method-clash.rs");
+    t.compile_fail("tests/40-sub-builder-pattern.rs");
+    t.compile_fail("tests/41-from-str-unparsable.rs");
 }